@@ -0,0 +1,151 @@
+//! Procedural macros of `evering`.
+
+use proc_macro::TokenStream;
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
+use syn::{Data, DeriveInput, Meta, parse_macro_input, parse_quote};
+
+/// Derives `evering::uring::ShmSafe` for a type whose fields are all
+/// `ShmSafe`.
+///
+/// Each type parameter is required to be `ShmSafe` as well. Fields of other
+/// types, such as `usize`, references or pointers to the heap, are rejected at
+/// compile time.
+///
+/// The type must have a stable layout, i.e. `#[repr(C)]`, `#[repr(transparent)]`
+/// or a fixed-width integer representation for enums, optionally along with
+/// `align` or `packed`,
+///
+/// ```
+/// use evering::uring::ShmSafe;
+///
+/// #[derive(ShmSafe)]
+/// #[repr(C, align(8))]
+/// struct Request {
+///     id: u32,
+///     data: Data,
+/// }
+///
+/// #[derive(ShmSafe)]
+/// #[repr(u32)]
+/// enum Data {
+///     Ping(i32),
+///     Exit,
+/// }
+/// ```
+///
+/// Types whose layout is left to the compiler are rejected,
+///
+/// ```compile_fail
+/// use evering::uring::ShmSafe;
+///
+/// #[derive(ShmSafe)]
+/// struct Request {
+///     id: u32,
+/// }
+/// ```
+///
+/// ```compile_fail
+/// use evering::uring::ShmSafe;
+///
+/// #[derive(ShmSafe)]
+/// #[repr(packed)]
+/// struct Request {
+///     id: u32,
+/// }
+/// ```
+///
+/// ```compile_fail
+/// use evering::uring::ShmSafe;
+///
+/// #[derive(ShmSafe)]
+/// #[repr(usize)]
+/// enum Data {
+///     Ping(i32),
+///     Exit,
+/// }
+/// ```
+#[proc_macro_derive(ShmSafe)]
+pub fn derive_shm_safe(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+
+    if let Err(e) = check_repr(&input) {
+        return e.to_compile_error().into();
+    }
+
+    let fields = match &input.data {
+        Data::Struct(s) => s.fields.iter().collect::<Vec<_>>(),
+        Data::Enum(e) => e.variants.iter().flat_map(|v| &v.fields).collect(),
+        Data::Union(u) => u.fields.named.iter().collect(),
+    };
+    let asserts = fields.iter().map(|f| {
+        let ty = &f.ty;
+        quote_spanned!(ty.span()=> assert_shm_safe::<#ty>();)
+    });
+
+    let mut generics = input.generics.clone();
+    for param in generics.type_params_mut() {
+        param.bounds.push(parse_quote!(::evering::uring::ShmSafe));
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    quote! {
+        // SAFETY: all fields are checked to be `ShmSafe` below.
+        unsafe impl #impl_generics ::evering::uring::ShmSafe for #name #ty_generics
+        #where_clause
+        {}
+
+        const _: () = {
+            fn assert_shm_safe<T: ?Sized + ::evering::uring::ShmSafe>() {}
+            #[allow(unused)]
+            fn assert_fields #impl_generics () #where_clause {
+                #(#asserts)*
+            }
+        };
+    }
+    .into()
+}
+
+/// Checks that the layout of a type is specified, so that both sides of the
+/// shared memory agree on it.
+fn check_repr(input: &DeriveInput) -> syn::Result<()> {
+    const STABLE: &[&str] = &["C", "transparent"];
+    // `usize` and `isize` vary by architecture.
+    const INTEGERS: &[&str] = &["u8", "u16", "u32", "u64", "i8", "i16", "i32", "i64"];
+    // Modifiers keep a specified layout specified, but never specify one on
+    // their own, e.g. `#[repr(align(8))]` alone is still laid out by rustc.
+    const MODIFIERS: &[&str] = &["align", "packed"];
+
+    let is_enum = matches!(input.data, Data::Enum(_));
+    let mut stable = false;
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("repr")) {
+        let Meta::List(list) = &attr.meta else {
+            continue;
+        };
+        list.parse_nested_meta(|meta| {
+            let path = &meta.path;
+            if STABLE.iter().any(|s| path.is_ident(s))
+                || is_enum && INTEGERS.iter().any(|s| path.is_ident(s))
+            {
+                stable = true;
+            } else if MODIFIERS.iter().any(|s| path.is_ident(s)) {
+                if meta.input.peek(syn::token::Paren) {
+                    let arg;
+                    syn::parenthesized!(arg in meta.input);
+                    arg.parse::<syn::LitInt>()?;
+                }
+            } else {
+                return Err(meta.error("unsupported representation for `ShmSafe`"));
+            }
+            Ok(())
+        })?;
+    }
+    if stable {
+        return Ok(());
+    }
+    Err(syn::Error::new_spanned(
+        &input.ident,
+        "`ShmSafe` requires a stable layout, e.g. `#[repr(C)]`",
+    ))
+}
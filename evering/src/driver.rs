@@ -1,43 +1,158 @@
 #![doc = include_str!("driver.md")]
 
+use alloc::collections::{BTreeMap, BTreeSet, VecDeque};
+use alloc::vec::Vec;
+use core::any::Any;
 use core::cell::RefCell;
-use core::mem;
 use core::task::{Context, LocalWaker, Poll};
+use core::time::Duration;
+use core::{fmt, mem};
 
 use slab::Slab;
 
 use crate::op::Cancellation;
 
-#[derive(Clone, Copy, Debug)]
-pub struct OpId(usize);
+#[cfg(feature = "std")]
+mod sync;
+
+#[cfg(feature = "std")]
+pub use self::sync::SyncDriver;
+
+/// The identifier of a submitted operation.
+///
+/// It is fixed-width so that it can be sent along with requests across
+/// processes. Besides the slot of the operation, it carries a generation which
+/// tells apart operations reusing the same slot, so that a stale or forged
+/// identifier never refers to another operation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(C)]
+pub struct OpId {
+    index: u32,
+    generation: u32,
+}
+
+impl OpId {
+    fn index(self) -> usize {
+        self.index as usize
+    }
+}
+
+// SAFETY: `OpId` consists of plain `u32`s.
+unsafe impl crate::uring::ShmSafe for OpId {}
+
+/// An error returned by [`Driver::complete`] and its variants, along with the
+/// given payload.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompleteError<P, Ext = ()> {
+    /// The operation has been cancelled, and it is recycled now along with the
+    /// submitted extension.
+    Cancelled(P, Ext),
+    /// There is no such operation, e.g. it has been completed and resolved.
+    Unknown(P),
+    /// The slot of the operation has been reused by another one.
+    Stale(P),
+    /// The operation has been completed, but not resolved yet.
+    Duplicate(P),
+}
+
+impl<P, Ext> CompleteError<P, Ext> {
+    /// Returns the payload given to the failed completion.
+    pub fn into_payload(self) -> P {
+        match self {
+            Self::Cancelled(p, _) | Self::Unknown(p) | Self::Stale(p) | Self::Duplicate(p) => p,
+        }
+    }
+
+    /// Returns `true` if the operation has been cancelled, in which case the
+    /// completion is expected and no error should be reported.
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self, Self::Cancelled(..))
+    }
+
+    fn without_ext(self) -> CompleteError<P> {
+        match self {
+            Self::Cancelled(p, _) => CompleteError::Cancelled(p, ()),
+            Self::Unknown(p) => CompleteError::Unknown(p),
+            Self::Stale(p) => CompleteError::Stale(p),
+            Self::Duplicate(p) => CompleteError::Duplicate(p),
+        }
+    }
+}
+
+impl<P, Ext> fmt::Display for CompleteError<P, Ext> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Cancelled(..) => "operation cancelled",
+            Self::Unknown(_) => "unknown operation",
+            Self::Stale(_) => "stale operation id",
+            Self::Duplicate(_) => "operation completed twice",
+        })
+    }
+}
+
+impl<P: fmt::Debug, Ext: fmt::Debug> core::error::Error for CompleteError<P, Ext> {}
+
+/// An error returned if the deadline of an operation elapsed before it was
+/// completed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Elapsed;
+
+impl fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("operation deadline elapsed")
+    }
+}
+
+impl core::error::Error for Elapsed {}
 
 pub struct Driver<P, Ext = ()>(RefCell<DriverInner<P, Ext>>);
 
-struct DriverInner<P, Ext> {
-    ops: Slab<RawOp<P, Ext>>,
+struct DriverInner<P, Ext, W = LocalWaker, R: ?Sized = dyn Any> {
+    ops: Slab<RawOp<P, Ext, W, R>>,
+    /// Deadlines of operations which have not expired yet.
+    timers: BTreeSet<(Duration, u32)>,
+    /// Cancelled operations which the responder has not been told about.
+    cancels: VecDeque<OpId>,
+    /// Generation of the next submitted operation.
+    generation: u32,
+    /// Shutdowns in progress, each registered by a `shutdown` future.
+    closing: Slab<Closing<W>>,
+}
+
+struct Closing<W> {
+    waker: Option<W>,
+    deadline: Option<Duration>,
+    elapsed: bool,
 }
 
-struct RawOp<P, Ext> {
-    state: Lifecycle<P>,
+struct RawOp<P, Ext, W, R: ?Sized> {
+    state: Lifecycle<P, W, R>,
+    generation: u32,
+    /// Payloads of a multishot operation received before the terminal one.
+    more: VecDeque<P>,
+    deadline: Option<Duration>,
     ext: Ext,
 }
 
-enum Lifecycle<P> {
+enum Lifecycle<P, W, R: ?Sized> {
     Submitted,
-    Waiting(LocalWaker),
+    Waiting(W),
     Completed(P),
-    Cancelled(#[allow(dead_code)] Cancellation),
+    /// The deadline elapsed, but the [`Op`](crate::op::Op) has not been
+    /// resolved yet.
+    Expired,
+    Cancelled(Cancellation<R>),
 }
 
 impl<P, Ext> Driver<P, Ext> {
     pub const fn new() -> Self {
-        Self(RefCell::new(DriverInner { ops: Slab::new() }))
+        Self(RefCell::new(DriverInner::new(Slab::new())))
     }
 
     pub fn with_capacity(capacity: usize) -> Self {
-        Self(RefCell::new(DriverInner {
-            ops: Slab::with_capacity(capacity),
-        }))
+        Self(RefCell::new(DriverInner::new(Slab::with_capacity(
+            capacity,
+        ))))
     }
 
     pub fn len(&self) -> usize {
@@ -49,7 +164,7 @@ impl<P, Ext> Driver<P, Ext> {
     }
 
     pub fn contains(&self, id: OpId) -> bool {
-        self.0.borrow().ops.contains(id.0)
+        self.0.borrow().get(id).is_ok()
     }
 
     pub fn submit(&self) -> OpId
@@ -76,33 +191,129 @@ impl<P, Ext> Driver<P, Ext> {
         self.0.borrow_mut().try_submit(ext)
     }
 
-    /// Completes a operation. It returns the given `payload` as an [`Err`] if
-    /// the specified operation has been cancelled.
+    /// Completes a operation. It returns the given `payload` in a
+    /// [`CompleteError`] if the specified operation has been cancelled, or the
+    /// given `id` is invalid.
     ///
     /// The given `id` is always recycled even if the corresponding operation is
-    /// cancelled.
-    pub fn complete(&self, id: OpId, payload: P) -> Result<(), P> {
+    /// cancelled. Invalid ids, e.g. those sent by a buggy responder, never
+    /// affect other operations.
+    pub fn complete(&self, id: OpId, payload: P) -> Result<(), CompleteError<P>> {
         self.0
             .borrow_mut()
             .complete(id, payload)
-            .map_err(|(p, _)| p)
+            .map_err(CompleteError::without_ext)
+    }
+
+    /// Delivers an intermediate payload to a multishot operation, which stays
+    /// alive until it is completed by [`complete`](Self::complete). It returns
+    /// the given `payload` in a [`CompleteError`] if the operation has been
+    /// cancelled, or the given `id` is invalid.
+    ///
+    /// See [`OpStream`](crate::op::OpStream) for more information.
+    pub fn complete_more(&self, id: OpId, payload: P) -> Result<(), CompleteError<P>> {
+        self.0.borrow_mut().complete_more(id, payload)
     }
 
     /// Completes a operation with the submitted extension.
     ///
     /// For more information, see [`complete`](Self::complete).
-    pub fn complete_ext(&self, id: OpId, payload: P) -> Result<(), (P, Ext)> {
+    pub fn complete_ext(&self, id: OpId, payload: P) -> Result<(), CompleteError<P, Ext>> {
         self.0.borrow_mut().complete(id, payload)
     }
 
-    pub(crate) fn poll(&self, id: OpId, cx: &mut Context) -> Poll<(P, Ext)> {
+    /// Completes all pending operations with payloads returned by `f`, and
+    /// returns the number of completed operations.
+    ///
+    /// This is useful to fail in-flight operations once the remote side is
+    /// gone, in which case they would never be completed.
+    pub fn complete_all(&self, f: impl FnMut(OpId) -> P) -> usize {
+        self.0.borrow_mut().complete_all(f)
+    }
+
+    /// Expires all operations whose deadlines are not later than `now`, and
+    /// returns the number of them.
+    ///
+    /// Deadlines are measured from an arbitrary epoch chosen by the caller,
+    /// e.g. the start of the runtime. Expired operations are woken up and
+    /// resolved by [`Expirable::expired`](crate::op::Expirable::expired),
+    /// unless they are completed before being polled again.
+    pub fn expire(&self, now: Duration) -> usize {
+        self.0.borrow_mut().expire(now)
+    }
+
+    /// Returns the earliest deadline which has not expired yet, until when the
+    /// caller may sleep before calling [`expire`](Self::expire).
+    pub fn next_deadline(&self) -> Option<Duration> {
+        self.0.borrow().next_deadline()
+    }
+
+    /// Takes the next operation which was cancelled by
+    /// [`Cancellation::remote`], for which the caller should send a cancel
+    /// entry to the responder.
+    ///
+    /// The operation is still kept until it is completed. Once completed, it is
+    /// no longer returned here, so that its [`OpId`] can be reused safely.
+    pub fn take_cancel(&self) -> Option<OpId> {
+        self.0.borrow_mut().cancels.pop_front()
+    }
+
+    /// Returns the number of operations to be taken by
+    /// [`take_cancel`](Self::take_cancel).
+    pub fn pending_cancels(&self) -> usize {
+        self.0.borrow().cancels.len()
+    }
+
+    /// Waits until all operations are completed, so that this driver can be
+    /// dropped. In-flight operations are still awaited by their owners, while
+    /// cancelled ones are waiting for completions to recycle their resources.
+    ///
+    /// If `deadline` is given, it fails with [`Elapsed`] once
+    /// [`expire`](Self::expire) reaches it, after which the owner may reclaim
+    /// orphaned resources with [`force_close`](Self::force_close).
+    ///
+    /// Multiple shutdowns may be in progress at once. Dropping the returned
+    /// future aborts its own shutdown, leaving the driver usable as before.
+    pub async fn shutdown(&self, deadline: Option<Duration>) -> Result<(), Elapsed> {
+        let key = self.0.borrow_mut().start_shutdown(deadline);
+        let _guard = OnDrop(|| _ = self.0.borrow_mut().closing.remove(key));
+        core::future::poll_fn(|cx| self.0.borrow_mut().poll_shutdown(key, cx)).await
+    }
+
+    /// Removes all cancelled operations which have not been completed, and
+    /// hands back their [`Cancellation`]s.
+    ///
+    /// The responder may still be using the resources, e.g. writing to shared
+    /// buffers, hence it is up to the caller to decide whether to drop or leak
+    /// them. Late completions of these operations are rejected as
+    /// [`CompleteError::Unknown`] or [`CompleteError::Stale`].
+    pub fn force_close(&self) -> Vec<(OpId, Cancellation)> {
+        let mut orphans = Vec::new();
+        self.0
+            .borrow_mut()
+            .force_close(|id, c| orphans.push((id, c)));
+        orphans
+    }
+}
+
+impl<P, Ext> private::Sealed<P, Ext, Cancellation> for Driver<P, Ext> {
+    fn set_deadline(&self, id: OpId, deadline: Duration) {
+        self.0.borrow_mut().set_deadline(id, deadline)
+    }
+
+    fn poll(&self, id: OpId, cx: &mut Context) -> Poll<Result<(P, Ext), Elapsed>> {
         self.0.borrow_mut().poll(id, cx)
     }
 
-    pub(crate) fn remove(&self, id: OpId, mut callback: impl FnMut() -> Cancellation) {
-        self.0.borrow_mut().remove(id, &mut callback)
+    fn poll_next(&self, id: OpId, cx: &mut Context) -> Poll<Result<(P, bool), Elapsed>> {
+        self.0.borrow_mut().poll_next(id, cx)
+    }
+
+    fn remove(&self, id: OpId, callback: &mut dyn FnMut() -> Cancellation) {
+        self.0.borrow_mut().remove(id, callback)
     }
 }
+impl<P, Ext> OpDriver<P, Ext, Cancellation> for Driver<P, Ext> {}
 
 impl<P, Ext> Default for Driver<P, Ext>
 where
@@ -113,12 +324,50 @@ where
     }
 }
 
-impl<P, Ext> DriverInner<P, Ext> {
+impl<P, Ext, W: OpWaker, R: ?Sized> DriverInner<P, Ext, W, R> {
+    const fn new(ops: Slab<RawOp<P, Ext, W, R>>) -> Self {
+        Self {
+            ops,
+            timers: BTreeSet::new(),
+            cancels: VecDeque::new(),
+            generation: 0,
+            closing: Slab::new(),
+        }
+    }
+
     fn submit(&mut self, ext: Ext) -> OpId {
-        OpId(self.ops.insert(RawOp {
+        let index = u32::try_from(self.ops.vacant_key()).expect("too many operations");
+        let generation = self.generation;
+        self.generation = generation.wrapping_add(1);
+        self.ops.insert(RawOp {
             state: Lifecycle::Submitted,
+            generation,
+            more: VecDeque::new(),
+            deadline: None,
             ext,
-        }))
+        });
+        OpId { index, generation }
+    }
+
+    /// Returns the operation of the given `id`, or the reason why it is
+    /// invalid.
+    fn get(&self, id: OpId) -> Result<&RawOp<P, Ext, W, R>, fn(P) -> CompleteError<P, Ext>> {
+        match self.ops.get(id.index()) {
+            None => Err(CompleteError::Unknown),
+            Some(op) if op.generation != id.generation => Err(CompleteError::Stale),
+            Some(op) => Ok(op),
+        }
+    }
+
+    fn get_mut(
+        &mut self,
+        id: OpId,
+    ) -> Result<&mut RawOp<P, Ext, W, R>, fn(P) -> CompleteError<P, Ext>> {
+        match self.ops.get_mut(id.index()) {
+            None => Err(CompleteError::Unknown),
+            Some(op) if op.generation != id.generation => Err(CompleteError::Stale),
+            Some(op) => Ok(op),
+        }
     }
 
     fn try_submit(&mut self, ext: Ext) -> Result<OpId, Ext> {
@@ -129,15 +378,88 @@ impl<P, Ext> DriverInner<P, Ext> {
         }
     }
 
-    fn poll(&mut self, id: OpId, cx: &mut Context) -> Poll<(P, Ext)> {
-        let op = self.ops.get_mut(id.0).expect("invalid driver state");
+    fn set_deadline(&mut self, id: OpId, deadline: Duration) {
+        let op = self.get_mut(id).expect("invalid driver state");
+        if let Some(prev) = op.deadline.replace(deadline) {
+            self.timers.remove(&(prev, id.index));
+        }
+        self.timers.insert((deadline, id.index));
+    }
+
+    fn expire(&mut self, now: Duration) -> usize {
+        let mut n = 0;
+        while let Some(&(deadline, id)) = self.timers.first() {
+            if deadline > now {
+                break;
+            }
+            self.timers.pop_first();
+            let op = &mut self.ops[id as usize];
+            op.deadline = None;
+            match mem::replace(&mut op.state, Lifecycle::Expired) {
+                Lifecycle::Submitted => {},
+                Lifecycle::Waiting(waker) => waker.wake(),
+                // Resources of cancelled operations are still recycled once
+                // they are completed.
+                state => {
+                    op.state = state;
+                    continue;
+                },
+            }
+            n += 1;
+        }
+        for (_, closing) in self.closing.iter_mut() {
+            if !closing.elapsed && closing.deadline.is_some_and(|d| d <= now) {
+                closing.elapsed = true;
+                closing.wake();
+            }
+        }
+        n
+    }
+
+    fn next_deadline(&self) -> Option<Duration> {
+        let closing = self
+            .closing
+            .iter()
+            .filter(|(_, c)| !c.elapsed)
+            .filter_map(|(_, c)| c.deadline)
+            .min();
+        let timer = self.timers.first().map(|&(deadline, _)| deadline);
+        match (timer, closing) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    /// Polls a multishot operation, which yields intermediate payloads before
+    /// the terminal one. The returned flag is `true` for the terminal payload.
+    fn poll_next(&mut self, id: OpId, cx: &mut Context) -> Poll<Result<(P, bool), Elapsed>> {
+        let op = self.get_mut(id).expect("invalid driver state");
+        // Intermediate payloads are checked along with the state, so that none
+        // of them is lost or missed by the waker.
+        if let Some(payload) = op.more.pop_front() {
+            return Poll::Ready(Ok((payload, false)));
+        }
+        self.poll(id, cx).map_ok(|(payload, _)| (payload, true))
+    }
+
+    /// Removes an operation along with its deadline.
+    fn take(&mut self, id: OpId) -> RawOp<P, Ext, W, R> {
+        let op = self.ops.remove(id.index());
+        if let Some(deadline) = op.deadline {
+            self.timers.remove(&(deadline, id.index));
+        }
+        op
+    }
+
+    fn poll(&mut self, id: OpId, cx: &mut Context) -> Poll<Result<(P, Ext), Elapsed>> {
+        let op = self.get_mut(id).expect("invalid driver state");
         match mem::replace(&mut op.state, Lifecycle::Submitted) {
             Lifecycle::Submitted => {
-                op.state = Lifecycle::Waiting(cx.local_waker().clone());
+                op.state = Lifecycle::Waiting(W::of(cx).clone());
                 Poll::Pending
             },
-            Lifecycle::Waiting(waker) if !waker.will_wake(cx.local_waker()) => {
-                op.state = Lifecycle::Waiting(cx.local_waker().clone());
+            Lifecycle::Waiting(waker) if !waker.will_wake(W::of(cx)) => {
+                op.state = Lifecycle::Waiting(W::of(cx).clone());
                 Poll::Pending
             },
             Lifecycle::Waiting(waker) => {
@@ -146,17 +468,28 @@ impl<P, Ext> DriverInner<P, Ext> {
             },
             Lifecycle::Completed(payload) => {
                 // Remove this operation immediately if completed.
-                let op = self.ops.remove(id.0);
-                Poll::Ready((payload, op.ext))
+                let op = self.take(id);
+                Poll::Ready(Ok((payload, op.ext)))
+            },
+            // Kept until the resources are recycled through `remove`.
+            Lifecycle::Expired => {
+                op.state = Lifecycle::Expired;
+                Poll::Ready(Err(Elapsed))
             },
             Lifecycle::Cancelled(_) => unreachable!("invalid operation state"),
         }
     }
 
-    fn complete(&mut self, id: OpId, payload: P) -> Result<(), (P, Ext)> {
-        let op = self.ops.get_mut(id.0).expect("invalid driver state");
+    fn complete(&mut self, id: OpId, payload: P) -> Result<(), CompleteError<P, Ext>> {
+        self.closing.iter().for_each(|(_, c)| c.wake());
+        let op = match self.get_mut(id) {
+            Ok(op) => op,
+            Err(e) => return Err(e(payload)),
+        };
         match mem::replace(&mut op.state, Lifecycle::Submitted) {
-            Lifecycle::Submitted => {
+            // An expired operation still takes the payload if it has not been
+            // resolved yet.
+            Lifecycle::Submitted | Lifecycle::Expired => {
                 op.state = Lifecycle::Completed(payload);
                 Ok(())
             },
@@ -165,44 +498,267 @@ impl<P, Ext> DriverInner<P, Ext> {
                 waker.wake();
                 Ok(())
             },
-            Lifecycle::Completed(_) => unreachable!("invalid operation state"),
-            Lifecycle::Cancelled(_) => {
-                let op = self.ops.remove(id.0);
-                Err((payload, op.ext))
+            state @ Lifecycle::Completed(_) => {
+                op.state = state;
+                Err(CompleteError::Duplicate(payload))
+            },
+            Lifecycle::Cancelled(c) => {
+                if c.is_remote() {
+                    self.cancels.retain(|&i| i != id);
+                }
+                let op = self.take(id);
+                Err(CompleteError::Cancelled(payload, op.ext))
+            },
+        }
+    }
+
+    fn complete_more(&mut self, id: OpId, payload: P) -> Result<(), CompleteError<P>> {
+        let op = match self.get_mut(id) {
+            Ok(op) => op,
+            Err(e) => return Err(e(payload).without_ext()),
+        };
+        match &op.state {
+            Lifecycle::Submitted | Lifecycle::Expired => op.more.push_back(payload),
+            Lifecycle::Waiting(waker) => {
+                op.more.push_back(payload);
+                waker.wake_by_ref();
             },
+            Lifecycle::Completed(_) => return Err(CompleteError::Duplicate(payload)),
+            // Resources are recycled by the terminal completion.
+            Lifecycle::Cancelled(_) => return Err(CompleteError::Cancelled(payload, ())),
+        }
+        Ok(())
+    }
+
+    fn complete_all(&mut self, mut f: impl FnMut(OpId) -> P) -> usize {
+        let pending = self
+            .ops
+            .iter()
+            .filter(|(_, op)| !matches!(op.state, Lifecycle::Completed(_)))
+            .map(|(index, op)| OpId {
+                index: index as u32,
+                generation: op.generation,
+            })
+            .collect::<Vec<_>>();
+        let mut n = 0;
+        for id in pending {
+            n += self.complete(id, f(id)).map_or(0, |_| 1);
+        }
+        n
+    }
+
+    /// Returns `true` if every operation has been completed, i.e. the driver
+    /// can be dropped.
+    fn is_settled(&self) -> bool {
+        self.ops
+            .iter()
+            .all(|(_, op)| matches!(op.state, Lifecycle::Completed(_)))
+    }
+
+    /// Registers a shutdown, which is removed from `closing` with the returned
+    /// key once it is finished or aborted.
+    fn start_shutdown(&mut self, deadline: Option<Duration>) -> usize {
+        self.closing.insert(Closing {
+            waker: None,
+            deadline,
+            elapsed: false,
+        })
+    }
+
+    fn poll_shutdown(&mut self, key: usize, cx: &mut Context) -> Poll<Result<(), Elapsed>> {
+        let settled = self.is_settled();
+        let closing = &mut self.closing[key];
+        if settled {
+            Poll::Ready(Ok(()))
+        } else if closing.elapsed {
+            Poll::Ready(Err(Elapsed))
+        } else {
+            match &closing.waker {
+                Some(waker) if waker.will_wake(W::of(cx)) => {},
+                _ => closing.waker = Some(W::of(cx).clone()),
+            }
+            Poll::Pending
+        }
+    }
+
+    /// Removes all cancelled operations, which will never be completed.
+    fn force_close(&mut self, mut f: impl FnMut(OpId, Cancellation<R>)) {
+        let cancelled = self
+            .ops
+            .iter()
+            .filter(|(_, op)| matches!(op.state, Lifecycle::Cancelled(_)))
+            .map(|(index, op)| OpId {
+                index: index as u32,
+                generation: op.generation,
+            })
+            .collect::<Vec<_>>();
+        for id in cancelled {
+            let Lifecycle::Cancelled(c) = self.take(id).state else {
+                unreachable!("invalid operation state")
+            };
+            f(id, c);
         }
+        self.cancels.clear();
+        self.closing.iter().for_each(|(_, c)| c.wake());
     }
 
-    fn remove(&mut self, id: OpId, callback: &mut dyn FnMut() -> Cancellation) {
-        // The operation may have been removed inside `poll`.
-        let Some(op) = self.ops.get_mut(id.0) else {
+    fn remove(&mut self, id: OpId, callback: &mut dyn FnMut() -> Cancellation<R>) {
+        // The operation may have been removed inside `poll`, and its slot may
+        // have been reused since then.
+        let Ok(op) = self.get_mut(id) else {
             return;
         };
         match mem::replace(&mut op.state, Lifecycle::Submitted) {
-            Lifecycle::Submitted | Lifecycle::Waiting(_) => {
-                op.state = Lifecycle::Cancelled(callback());
+            Lifecycle::Submitted | Lifecycle::Waiting(_) | Lifecycle::Expired => {
+                let cancellation = callback();
+                let remote = cancellation.is_remote();
+                op.state = Lifecycle::Cancelled(cancellation);
+                op.more.clear();
+                if remote {
+                    self.cancels.push_back(id);
+                }
             },
-            Lifecycle::Completed(_) => _ = self.ops.remove(id.0),
+            Lifecycle::Completed(_) => _ = self.take(id),
             Lifecycle::Cancelled(_) => unreachable!("invalid operation state"),
         }
     }
 }
 
-impl<P, Ext> Drop for DriverInner<P, Ext> {
+impl<W: OpWaker> Closing<W> {
+    fn wake(&self) {
+        if let Some(waker) = &self.waker {
+            waker.wake_by_ref();
+        }
+    }
+}
+
+/// Runs the closure when dropped, e.g. when a future is cancelled.
+struct OnDrop<F: FnMut()>(F);
+
+impl<F: FnMut()> Drop for OnDrop<F> {
+    fn drop(&mut self) {
+        (self.0)()
+    }
+}
+
+impl<P, Ext, W, R: ?Sized> Drop for DriverInner<P, Ext, W, R> {
     fn drop(&mut self) {
         assert!(
             self.ops
                 .iter()
                 .all(|(_, op)| matches!(op.state, Lifecycle::Completed(_))),
-            "all operations inside `Driver` must be completed before dropping"
+            "all operations inside `Driver` must be completed before dropping, \
+             see `Driver::shutdown`"
         );
     }
 }
 
+/// Tracks requests being handled by the responder, and whether they have been
+/// cancelled by the requester.
+///
+/// Cancel entries sent for [`Driver::take_cancel`] may arrive after the
+/// request is responded, in which case they are ignored.
+pub struct Responder(RefCell<BTreeMap<OpId, bool>>);
+
+impl Responder {
+    pub const fn new() -> Self {
+        Self(RefCell::new(BTreeMap::new()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.borrow().is_empty()
+    }
+
+    /// Starts handling a request.
+    pub fn accept(&self, id: OpId) {
+        let prev = self.0.borrow_mut().insert(id, false);
+        debug_assert!(prev.is_none(), "request accepted twice");
+    }
+
+    /// Marks a request as cancelled. It returns `false` if the request is not
+    /// being handled, e.g. it has been responded.
+    pub fn cancel(&self, id: OpId) -> bool {
+        self.0
+            .borrow_mut()
+            .get_mut(&id)
+            .map(|cancelled| *cancelled = true)
+            .is_some()
+    }
+
+    pub fn is_cancelled(&self, id: OpId) -> bool {
+        self.0.borrow().get(&id).copied().unwrap_or(false)
+    }
+
+    /// Stops handling a request before responding to it, and returns whether
+    /// it has been cancelled.
+    pub fn finish(&self, id: OpId) -> bool {
+        self.0.borrow_mut().remove(&id).unwrap_or(false)
+    }
+}
+
+impl Default for Responder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Wakers stored by a driver until operations are completed.
+trait OpWaker: Clone {
+    fn of<'a>(cx: &'a Context) -> &'a Self;
+    fn will_wake(&self, other: &Self) -> bool;
+    fn wake(self);
+    fn wake_by_ref(&self);
+}
+
+impl OpWaker for LocalWaker {
+    fn of<'a>(cx: &'a Context) -> &'a Self {
+        cx.local_waker()
+    }
+    fn will_wake(&self, other: &Self) -> bool {
+        self.will_wake(other)
+    }
+    fn wake(self) {
+        self.wake()
+    }
+    fn wake_by_ref(&self) {
+        self.wake_by_ref()
+    }
+}
+
+pub(crate) mod private {
+    use super::*;
+
+    /// Methods used by [`Op`](crate::op::Op) and its variants.
+    pub trait Sealed<P, Ext, C> {
+        fn set_deadline(&self, id: OpId, deadline: Duration);
+        fn poll(&self, id: OpId, cx: &mut Context) -> Poll<Result<(P, Ext), Elapsed>>;
+        fn poll_next(&self, id: OpId, cx: &mut Context) -> Poll<Result<(P, bool), Elapsed>>;
+        fn remove(&self, id: OpId, callback: &mut dyn FnMut() -> C);
+    }
+}
+
+/// A driver which operations are submitted to, i.e. [`Driver`] or
+/// `SyncDriver` if the `std` feature is enabled.
+///
+/// Resources of cancelled operations are submitted as `C`, i.e.
+/// [`Cancellation`] or [`SendCancellation`] respectively.
+///
+/// [`SendCancellation`]: crate::op::SendCancellation
+pub trait OpDriver<P, Ext, C>: private::Sealed<P, Ext, C> {}
+
 pub trait DriverHandle: 'static + Unpin {
     type Payload;
     type Ext;
-    type Ref: core::ops::Deref<Target = Driver<Self::Payload, Self::Ext>>;
+    /// The type returned by [`Completable::cancel`], which is determined by
+    /// the driver.
+    ///
+    /// [`Completable::cancel`]: crate::op::Completable::cancel
+    type Cancellation;
+    type Ref: core::ops::Deref<Target: OpDriver<Self::Payload, Self::Ext, Self::Cancellation>>;
 
     fn get(&self) -> Self::Ref;
 }
@@ -213,8 +769,445 @@ where
 {
     type Payload = P;
     type Ext = Ext;
+    type Cancellation = Cancellation;
     type Ref = alloc::rc::Rc<Driver<P, Ext>>;
     fn get(&self) -> Self::Ref {
         self.upgrade().expect("not inside a valid executor")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::async_iter::AsyncIterator;
+    use std::cell::Cell;
+    use std::collections::BTreeMap;
+    use std::pin::{Pin, pin};
+    use std::rc::{Rc, Weak};
+    use std::task::Waker;
+
+    use proptest::prelude::*;
+
+    use super::private::Sealed as _;
+    use super::*;
+    use crate::op::{Chain, Completable, Expirable, Multishot, Op, OpStream};
+
+    struct Recycled(Rc<Cell<bool>>);
+    impl Drop for Recycled {
+        fn drop(&mut self) {
+            self.0.set(true);
+        }
+    }
+
+    /// An operation which fails on a zero payload, and whose resource is
+    /// recycled on cancellation.
+    struct Ping {
+        recycled: Rc<Cell<bool>>,
+        remote: bool,
+    }
+    impl Ping {
+        fn new(recycled: &Rc<Cell<bool>>) -> Self {
+            Self {
+                recycled: recycled.clone(),
+                remote: false,
+            }
+        }
+
+        /// Also sends a cancel entry to the responder once dropped.
+        fn remote(recycled: &Rc<Cell<bool>>) -> Self {
+            Self {
+                remote: true,
+                ..Self::new(recycled)
+            }
+        }
+
+        fn recycle(self) -> Cancellation {
+            let c = Cancellation::recycle(Recycled(self.recycled));
+            if self.remote { c.remote() } else { c }
+        }
+    }
+    unsafe impl Completable for Ping {
+        type Output = Result<u8, &'static str>;
+        type Driver = Weak<Driver<u8>>;
+        fn complete(self, _: &Self::Driver, payload: u8) -> Self::Output {
+            if payload == 0 {
+                Err("failed")
+            } else {
+                Ok(payload)
+            }
+        }
+        fn cancel(self, _: &Self::Driver) -> Cancellation {
+            self.recycle()
+        }
+    }
+    impl Expirable for Ping {
+        fn expired(_: &Self::Driver) -> Self::Output {
+            Err("elapsed")
+        }
+    }
+    unsafe impl Multishot for Ping {
+        type Item = u8;
+        type Driver = Weak<Driver<u8>>;
+        fn next(&mut self, _: &Self::Driver, payload: u8) -> u8 {
+            payload
+        }
+        fn cancel(self, _: &Self::Driver) -> Cancellation {
+            self.recycle()
+        }
+    }
+
+    #[test]
+    fn op_chain() {
+        let drv = Rc::new(Driver::<u8>::new());
+        let mut cx = Context::from_waker(Waker::noop());
+        let recycled = Rc::new(Cell::new(false));
+        let submit = || {
+            let id = drv.submit();
+            (id, Op::new(Rc::downgrade(&drv), id, Ping::new(&recycled)))
+        };
+
+        let ((a, op_a), (b, op_b), (c, op_c)) = (submit(), submit(), submit());
+        let mut chain = pin!(Chain::new((op_a, op_b, op_c)));
+        drv.complete(c, 3).unwrap();
+        drv.complete(b, 2).unwrap();
+        assert_eq!(chain.as_mut().poll(&mut cx), Poll::Pending);
+        drv.complete(a, 1).unwrap();
+        assert_eq!(chain.as_mut().poll(&mut cx), Poll::Ready(Ok((1, 2, 3))));
+        assert!(drv.is_empty());
+
+        let ((a, op_a), (b, op_b), (c, op_c)) = (submit(), submit(), submit());
+        let mut chain = Chain::new((op_a, op_b, op_c));
+        drv.complete(b, 0).unwrap();
+        // Wait for earlier links before reporting the error.
+        assert_eq!(Pin::new(&mut chain).poll(&mut cx), Poll::Pending);
+        drv.complete(a, 1).unwrap();
+        assert_eq!(
+            Pin::new(&mut chain).poll(&mut cx),
+            Poll::Ready(Err("failed"))
+        );
+        // The remaining links are cancelled along with the chain.
+        drop(chain);
+        assert!(drv.contains(c) && !recycled.get());
+        assert_eq!(drv.complete(c, 0), Err(CompleteError::Cancelled(0, ())));
+        assert!(drv.is_empty() && recycled.get());
+    }
+
+    #[test]
+    fn op_stale_id() {
+        let drv = Driver::<u8>::new();
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let a = drv.submit();
+        drv.complete(a, 1).unwrap();
+        assert_eq!(drv.complete(a, 2), Err(CompleteError::Duplicate(2)));
+        assert_eq!(drv.poll(a, &mut cx), Poll::Ready(Ok((1, ()))));
+        assert_eq!(drv.complete(a, 3), Err(CompleteError::Unknown(3)));
+
+        // The slot of `a` is reused, which is not affected by `a` anymore.
+        let b = drv.submit();
+        assert_eq!(a.index, b.index);
+        assert!(!drv.contains(a));
+        assert_eq!(drv.complete(a, 4), Err(CompleteError::Stale(4)));
+        drv.remove(a, &mut || unreachable!());
+        assert_eq!(drv.poll(b, &mut cx), Poll::Pending);
+        drv.complete(b, 5).unwrap();
+        assert_eq!(drv.poll(b, &mut cx), Poll::Ready(Ok((5, ()))));
+    }
+
+    #[test]
+    fn op_remote_cancel() {
+        let drv = Rc::new(Driver::<u8>::new());
+        let responder = Responder::new();
+        let recycled = Rc::new(Cell::new(false));
+
+        let (a, b) = (drv.submit(), drv.submit());
+        responder.accept(a);
+        responder.accept(b);
+        drop(Op::new(Rc::downgrade(&drv), a, Ping::remote(&recycled)));
+        drop(Op::new(Rc::downgrade(&drv), b, Ping::remote(&recycled)));
+
+        // The cancel entry of `a` is delivered before it is responded.
+        assert_eq!(drv.take_cancel(), Some(a));
+        assert!(responder.cancel(a) && responder.is_cancelled(a));
+        assert!(!responder.is_cancelled(b));
+        assert!(responder.finish(a));
+        assert!(drv.complete(a, 1).is_err());
+        assert!(drv.contains(b) && recycled.get());
+
+        // `b` is responded before its cancel entry is sent.
+        assert!(!responder.finish(b));
+        assert!(drv.complete(b, 2).is_err());
+        assert_eq!(drv.pending_cancels(), 0);
+        // A late cancel entry is ignored by the responder.
+        assert!(!responder.cancel(b));
+        assert!(drv.is_empty() && responder.is_empty());
+    }
+
+    #[test]
+    fn driver_shutdown() {
+        let drv = Rc::new(Driver::<u8>::new());
+        let mut cx = Context::from_waker(Waker::noop());
+        let recycled = Rc::new(Cell::new(false));
+
+        let (a, b) = (drv.submit(), drv.submit());
+        let mut op_a = Op::new(Rc::downgrade(&drv), a, Ping::remote(&recycled));
+        drop(Op::new(Rc::downgrade(&drv), b, Ping::remote(&recycled)));
+
+        // Dropping a future aborts its own shutdown only.
+        let mut aborted = Box::pin(drv.shutdown(Some(Duration::from_millis(1))));
+        let mut other = Box::pin(drv.shutdown(None));
+        assert_eq!(aborted.as_mut().poll(&mut cx), Poll::Pending);
+        assert_eq!(other.as_mut().poll(&mut cx), Poll::Pending);
+        assert_eq!(drv.next_deadline(), Some(Duration::from_millis(1)));
+        drop(aborted);
+        assert_eq!(drv.next_deadline(), None);
+        assert_eq!(other.as_mut().poll(&mut cx), Poll::Pending);
+        drop(other);
+
+        let mut shutdown = pin!(drv.shutdown(Some(Duration::from_secs(1))));
+        assert_eq!(shutdown.as_mut().poll(&mut cx), Poll::Pending);
+        assert_eq!(drv.next_deadline(), Some(Duration::from_secs(1)));
+        drv.complete(a, 1).unwrap();
+        assert_eq!(shutdown.as_mut().poll(&mut cx), Poll::Pending);
+        drv.expire(Duration::from_secs(1));
+        assert_eq!(shutdown.as_mut().poll(&mut cx), Poll::Ready(Err(Elapsed)));
+        assert_eq!(drv.next_deadline(), None);
+
+        // `b` is never completed, and its resource is reclaimed explicitly.
+        let orphans = drv.force_close();
+        assert_eq!(orphans.len(), 1);
+        assert_eq!(drv.pending_cancels(), 0);
+        let (id, c) = orphans.into_iter().next().unwrap();
+        assert_eq!(id, b);
+        assert!(!recycled.get());
+        assert!(c.into_resource().unwrap().is::<Recycled>());
+        assert!(recycled.get());
+        assert_eq!(drv.complete(b, 2), Err(CompleteError::Unknown(2)));
+
+        // `a` is completed, hence the driver can be dropped.
+        let mut shutdown = pin!(drv.shutdown(None));
+        assert_eq!(shutdown.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        assert_eq!(Pin::new(&mut op_a).poll(&mut cx), Poll::Ready(Ok(1)));
+        assert!(drv.is_empty());
+    }
+
+    #[test]
+    fn op_stream() {
+        let drv = Rc::new(Driver::<u8>::new());
+        let mut cx = Context::from_waker(Waker::noop());
+        let recycled = Rc::new(Cell::new(false));
+        let subscribe = || {
+            let id = drv.submit();
+            (
+                id,
+                OpStream::new(Rc::downgrade(&drv), id, Ping::new(&recycled)),
+            )
+        };
+
+        let (id, stream) = subscribe();
+        let mut stream = pin!(stream);
+        assert_eq!(stream.as_mut().poll_next(&mut cx), Poll::Pending);
+        assert_eq!(drv.complete_more(id, 1), Ok(()));
+        assert_eq!(drv.complete_more(id, 2), Ok(()));
+        assert_eq!(stream.as_mut().poll_next(&mut cx), Poll::Ready(Some(1)));
+        assert_eq!(drv.complete(id, 3), Ok(()));
+        // Intermediate payloads are always received before the terminal one.
+        assert_eq!(stream.as_mut().poll_next(&mut cx), Poll::Ready(Some(2)));
+        assert_eq!(stream.as_mut().poll_next(&mut cx), Poll::Ready(Some(3)));
+        assert_eq!(stream.as_mut().poll_next(&mut cx), Poll::Ready(None));
+        assert!(drv.is_empty() && !recycled.get());
+
+        let (id, stream) = subscribe();
+        assert_eq!(drv.complete_more(id, 1), Ok(()));
+        drop(stream);
+        // Resources are recycled once the terminal payload arrives.
+        assert_eq!(
+            drv.complete_more(id, 2),
+            Err(CompleteError::Cancelled(2, ()))
+        );
+        assert!(drv.contains(id) && !recycled.get());
+        assert_eq!(drv.complete(id, 3), Err(CompleteError::Cancelled(3, ())));
+        assert!(drv.is_empty() && recycled.get());
+    }
+
+    #[test]
+    fn op_deadline() {
+        let drv = Rc::new(Driver::<u8>::new());
+        let mut cx = Context::from_waker(Waker::noop());
+        let recycled = Rc::new(Cell::new(false));
+        let submit = |ms| {
+            let id = drv.submit();
+            let ping = Ping::new(&recycled);
+            let op = Op::with_deadline(Rc::downgrade(&drv), id, ping, Duration::from_millis(ms));
+            (id, op)
+        };
+        let (id1, op1) = submit(10);
+        let (id2, op2) = submit(20);
+        let (id3, op3) = submit(30);
+        let (mut op1, mut op2, mut op3) = (pin!(op1), pin!(op2), pin!(op3));
+        assert!(op1.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(drv.next_deadline(), Some(Duration::from_millis(10)));
+
+        assert_eq!(drv.expire(Duration::from_millis(5)), 0);
+        assert_eq!(drv.expire(Duration::from_millis(10)), 1);
+        assert_eq!(op1.as_mut().poll(&mut cx), Poll::Ready(Err("elapsed")));
+        // Resources are recycled once the late completion arrives.
+        assert!(drv.contains(id1) && !recycled.get());
+        assert_eq!(drv.complete(id1, 1), Err(CompleteError::Cancelled(1, ())));
+        assert!(!drv.contains(id1) && recycled.get());
+
+        // Completed before the deadline.
+        assert_eq!(drv.complete(id2, 2), Ok(()));
+        assert_eq!(op2.as_mut().poll(&mut cx), Poll::Ready(Ok(2)));
+        assert_eq!(drv.next_deadline(), Some(Duration::from_millis(30)));
+
+        // Completed after expiration, but before being resolved.
+        assert_eq!(drv.expire(Duration::from_millis(30)), 1);
+        assert_eq!(drv.complete(id3, 3), Ok(()));
+        assert_eq!(op3.as_mut().poll(&mut cx), Poll::Ready(Ok(3)));
+        assert_eq!(drv.next_deadline(), None);
+        assert!(drv.is_empty());
+    }
+
+    #[derive(Clone, Debug)]
+    enum DriverOp {
+        Submit,
+        Poll(usize),
+        Complete(usize, u8),
+        CompleteRetired(usize, u8),
+        Remove(usize),
+        CompleteAll(u8),
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    enum State {
+        Submitted,
+        Waiting,
+        Completed(u8),
+        Cancelled,
+    }
+
+    fn driver_op() -> impl Strategy<Value = DriverOp> {
+        prop_oneof![
+            Just(DriverOp::Submit),
+            any::<usize>().prop_map(DriverOp::Poll),
+            (any::<usize>(), any::<u8>()).prop_map(|(i, p)| DriverOp::Complete(i, p)),
+            (any::<usize>(), any::<u8>()).prop_map(|(i, p)| DriverOp::CompleteRetired(i, p)),
+            any::<usize>().prop_map(DriverOp::Remove),
+            any::<u8>().prop_map(DriverOp::CompleteAll),
+        ]
+    }
+
+    /// Picks an operation whose state satisfies `f`.
+    fn pick(model: &BTreeMap<OpId, State>, i: usize, f: fn(State) -> bool) -> Option<OpId> {
+        let ids = model
+            .iter()
+            .filter(|&(_, &s)| f(s))
+            .map(|(&id, _)| id)
+            .collect::<Vec<_>>();
+        (!ids.is_empty()).then(|| ids[i % ids.len()])
+    }
+
+    proptest! {
+        /// Compares the lifecycle of operations against a simple model.
+        #[test]
+        fn driver_model(ops in proptest::collection::vec(driver_op(), 0..64)) {
+            let driver = Driver::<u8>::new();
+            let mut model = BTreeMap::new();
+            // Operations which have been recycled.
+            let mut retired = Vec::new();
+            let mut cx = Context::from_waker(Waker::noop());
+
+            for op in ops {
+                match op {
+                    DriverOp::Submit => {
+                        let id = driver.submit();
+                        prop_assert!(model.insert(id, State::Submitted).is_none());
+                    },
+                    DriverOp::Poll(i) => {
+                        let Some(id) = pick(&model, i, |s| s != State::Cancelled) else {
+                            continue;
+                        };
+                        let r = driver.poll(id, &mut cx);
+                        match model[&id] {
+                            State::Completed(p) => {
+                                prop_assert_eq!(r, Poll::Ready(Ok((p, ()))));
+                                model.remove(&id);
+                                retired.push(id);
+                            },
+                            _ => {
+                                prop_assert_eq!(r, Poll::Pending);
+                                model.insert(id, State::Waiting);
+                            },
+                        }
+                    },
+                    DriverOp::Complete(i, p) => {
+                        let Some(id) = pick(&model, i, |_| true) else {
+                            continue;
+                        };
+                        let r = driver.complete(id, p);
+                        match model[&id] {
+                            State::Cancelled => {
+                                prop_assert_eq!(r, Err(CompleteError::Cancelled(p, ())));
+                                model.remove(&id);
+                                retired.push(id);
+                            },
+                            State::Completed(_) => {
+                                prop_assert_eq!(r, Err(CompleteError::Duplicate(p)));
+                            },
+                            _ => {
+                                prop_assert_eq!(r, Ok(()));
+                                model.insert(id, State::Completed(p));
+                            },
+                        }
+                    },
+                    DriverOp::CompleteRetired(i, p) => {
+                        if retired.is_empty() {
+                            continue;
+                        }
+                        let id = retired[i % retired.len()];
+                        let r = driver.complete(id, p).unwrap_err();
+                        prop_assert!(matches!(
+                            r,
+                            CompleteError::Unknown(_) | CompleteError::Stale(_)
+                        ));
+                        prop_assert!(!driver.contains(id));
+                    },
+                    DriverOp::Remove(i) => {
+                        let Some(id) = pick(&model, i, |s| s != State::Cancelled) else {
+                            continue;
+                        };
+                        driver.remove(id, &mut Cancellation::noop);
+                        if let State::Completed(_) = model[&id] {
+                            model.remove(&id);
+                            retired.push(id);
+                        } else {
+                            model.insert(id, State::Cancelled);
+                        }
+                    },
+                    DriverOp::CompleteAll(p) => {
+                        let mut n = 0;
+                        model.retain(|&id, s| match s {
+                            State::Submitted | State::Waiting => {
+                                *s = State::Completed(p);
+                                n += 1;
+                                true
+                            },
+                            State::Completed(_) => true,
+                            State::Cancelled => {
+                                retired.push(id);
+                                false
+                            },
+                        });
+                        prop_assert_eq!(driver.complete_all(|_| p), n);
+                    },
+                }
+                prop_assert_eq!(driver.len(), model.len());
+                for &id in model.keys() {
+                    prop_assert!(driver.contains(id));
+                }
+            }
+            // All operations must be completed before dropping.
+            driver.complete_all(|_| 0);
+        }
+    }
+}
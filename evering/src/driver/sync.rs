@@ -0,0 +1,432 @@
+use alloc::boxed::Box;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::any::Any;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::task::{Context, Poll, Waker};
+use core::time::Duration;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use slab::Slab;
+
+use super::{
+    CompleteError, DriverHandle, DriverInner, Elapsed, OnDrop, OpDriver, OpId, OpWaker, private,
+};
+use crate::op::SendCancellation;
+
+impl OpWaker for Waker {
+    fn of<'a>(cx: &'a Context) -> &'a Self {
+        cx.waker()
+    }
+    fn will_wake(&self, other: &Self) -> bool {
+        self.will_wake(other)
+    }
+    fn wake(self) {
+        self.wake()
+    }
+    fn wake_by_ref(&self) {
+        self.wake_by_ref()
+    }
+}
+
+/// A thread-safe [`Driver`](super::Driver), which stores [`Waker`]s and can be
+/// shared by multi-threaded executors.
+///
+/// Operations are spread over shards guarded by separate locks, and the shard
+/// of an operation is encoded in its [`OpId`]. Resources of cancelled
+/// operations may be recycled on another thread, hence they are submitted as
+/// [`SendCancellation`]s.
+pub struct SyncDriver<P, Ext = ()> {
+    shards: Box<[Mutex<Shard<P, Ext>>]>,
+    next: AtomicUsize,
+}
+
+type Shard<P, Ext> = DriverInner<P, Ext, Waker, dyn Any + Send>;
+
+impl<P, Ext> SyncDriver<P, Ext> {
+    pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    /// Creates a driver with one shard per available thread, among which the
+    /// given `capacity` is split.
+    pub fn with_capacity(capacity: usize) -> Self {
+        let n = std::thread::available_parallelism().map_or(1, usize::from);
+        Self::with_shards(n, capacity)
+    }
+
+    pub fn with_shards(shards: usize, capacity: usize) -> Self {
+        assert!(shards > 0, "there must be at least one shard");
+        let shards = (0..shards)
+            .map(|i| {
+                let cap = capacity / shards + (i < capacity % shards) as usize;
+                Mutex::new(DriverInner::new(Slab::with_capacity(cap)))
+            })
+            .collect();
+        Self {
+            shards,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|s| lock(s).ops.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(|s| lock(s).ops.is_empty())
+    }
+
+    pub fn contains(&self, id: OpId) -> bool {
+        let (shard, id) = self.shard(id);
+        shard.get(id).is_ok()
+    }
+
+    pub fn submit(&self) -> OpId
+    where
+        Ext: Default,
+    {
+        self.submit_ext(Ext::default())
+    }
+
+    pub fn submit_ext(&self, ext: Ext) -> OpId {
+        let i = self.next.fetch_add(1, Ordering::Relaxed) % self.shards.len();
+        let id = lock(&self.shards[i]).submit(ext);
+        self.global(i, id)
+    }
+
+    /// Submits an operation if there is sufficient spare capacity in any shard,
+    /// otherwise an error is returned with the element.
+    pub fn try_submit(&self) -> Result<OpId, Ext>
+    where
+        Ext: Default,
+    {
+        self.try_submit_ext(Ext::default())
+    }
+
+    pub fn try_submit_ext(&self, mut ext: Ext) -> Result<OpId, Ext> {
+        let n = self.shards.len();
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        for i in (start..start + n).map(|i| i % n) {
+            match lock(&self.shards[i]).try_submit(ext) {
+                Ok(id) => return Ok(self.global(i, id)),
+                Err(e) => ext = e,
+            }
+        }
+        Err(ext)
+    }
+
+    /// See [`Driver::complete`](super::Driver::complete).
+    pub fn complete(&self, id: OpId, payload: P) -> Result<(), CompleteError<P>> {
+        self.complete_ext(id, payload)
+            .map_err(CompleteError::without_ext)
+    }
+
+    /// See [`Driver::complete_more`](super::Driver::complete_more).
+    pub fn complete_more(&self, id: OpId, payload: P) -> Result<(), CompleteError<P>> {
+        let (mut shard, id) = self.shard(id);
+        shard.complete_more(id, payload)
+    }
+
+    /// See [`Driver::complete_ext`](super::Driver::complete_ext).
+    pub fn complete_ext(&self, id: OpId, payload: P) -> Result<(), CompleteError<P, Ext>> {
+        let (mut shard, id) = self.shard(id);
+        shard.complete(id, payload)
+    }
+
+    /// See [`Driver::complete_all`](super::Driver::complete_all).
+    pub fn complete_all(&self, mut f: impl FnMut(OpId) -> P) -> usize {
+        let mut n = 0;
+        for (i, shard) in self.shards.iter().enumerate() {
+            n += lock(shard).complete_all(|id| f(self.global(i, id)));
+        }
+        n
+    }
+
+    /// See [`Driver::expire`](super::Driver::expire).
+    pub fn expire(&self, now: Duration) -> usize {
+        self.shards.iter().map(|s| lock(s).expire(now)).sum()
+    }
+
+    /// See [`Driver::next_deadline`](super::Driver::next_deadline).
+    pub fn next_deadline(&self) -> Option<Duration> {
+        self.shards
+            .iter()
+            .filter_map(|s| lock(s).next_deadline())
+            .min()
+    }
+
+    /// See [`Driver::take_cancel`](super::Driver::take_cancel).
+    pub fn take_cancel(&self) -> Option<OpId> {
+        self.shards.iter().enumerate().find_map(|(i, s)| {
+            let id = lock(s).cancels.pop_front()?;
+            Some(self.global(i, id))
+        })
+    }
+
+    /// See [`Driver::pending_cancels`](super::Driver::pending_cancels).
+    pub fn pending_cancels(&self) -> usize {
+        self.shards.iter().map(|s| lock(s).cancels.len()).sum()
+    }
+
+    /// See [`Driver::shutdown`](super::Driver::shutdown).
+    pub async fn shutdown(&self, deadline: Option<Duration>) -> Result<(), Elapsed> {
+        let keys = self
+            .shards
+            .iter()
+            .map(|s| lock(s).start_shutdown(deadline))
+            .collect::<Vec<_>>();
+        let _guard = OnDrop(|| {
+            for (shard, &key) in self.shards.iter().zip(&keys) {
+                lock(shard).closing.remove(key);
+            }
+        });
+        core::future::poll_fn(|cx| {
+            let mut settled = true;
+            for (shard, &key) in self.shards.iter().zip(&keys) {
+                match lock(shard).poll_shutdown(key, cx) {
+                    Poll::Ready(Ok(())) => {},
+                    Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                    Poll::Pending => settled = false,
+                }
+            }
+            if settled {
+                Poll::Ready(Ok(()))
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    /// See [`Driver::force_close`](super::Driver::force_close).
+    pub fn force_close(&self) -> Vec<(OpId, SendCancellation)> {
+        let mut orphans = Vec::new();
+        for (i, shard) in self.shards.iter().enumerate() {
+            lock(shard).force_close(|id, c| orphans.push((self.global(i, id), c)));
+        }
+        orphans
+    }
+
+    /// Returns the shard of an operation, along with its identifier inside the
+    /// shard.
+    fn shard(&self, id: OpId) -> (MutexGuard<'_, Shard<P, Ext>>, OpId) {
+        let n = self.shards.len() as u32;
+        let local = OpId {
+            index: id.index / n,
+            ..id
+        };
+        (lock(&self.shards[(id.index % n) as usize]), local)
+    }
+
+    fn global(&self, shard: usize, id: OpId) -> OpId {
+        let n = self.shards.len() as u32;
+        let index = id
+            .index
+            .checked_mul(n)
+            .and_then(|i| i.checked_add(shard as u32))
+            .expect("too many operations");
+        OpId { index, ..id }
+    }
+}
+
+impl<P, Ext> Default for SyncDriver<P, Ext> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<P, Ext> private::Sealed<P, Ext, SendCancellation> for SyncDriver<P, Ext> {
+    fn set_deadline(&self, id: OpId, deadline: Duration) {
+        let (mut shard, id) = self.shard(id);
+        shard.set_deadline(id, deadline)
+    }
+
+    fn poll(&self, id: OpId, cx: &mut Context) -> Poll<Result<(P, Ext), Elapsed>> {
+        let (mut shard, id) = self.shard(id);
+        shard.poll(id, cx)
+    }
+
+    fn poll_next(&self, id: OpId, cx: &mut Context) -> Poll<Result<(P, bool), Elapsed>> {
+        let (mut shard, id) = self.shard(id);
+        shard.poll_next(id, cx)
+    }
+
+    fn remove(&self, id: OpId, callback: &mut dyn FnMut() -> SendCancellation) {
+        let (mut shard, id) = self.shard(id);
+        shard.remove(id, callback)
+    }
+}
+impl<P, Ext> OpDriver<P, Ext, SendCancellation> for SyncDriver<P, Ext> {}
+
+impl<P, Ext> DriverHandle for Weak<SyncDriver<P, Ext>>
+where
+    P: 'static,
+    Ext: 'static,
+{
+    type Payload = P;
+    type Ext = Ext;
+    type Cancellation = SendCancellation;
+    type Ref = Arc<SyncDriver<P, Ext>>;
+    fn get(&self) -> Self::Ref {
+        self.upgrade().expect("not inside a valid executor")
+    }
+}
+
+/// Locks a shard, which is still consistent if another thread panicked while
+/// holding it.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::pin;
+    use std::sync::mpsc;
+    use std::task::Wake;
+    use std::thread::{self, Thread};
+
+    use super::*;
+    use crate::op::{Completable, Multishot, Op, OpStream, SendCancellation};
+
+    struct Echo;
+    unsafe impl Completable for Echo {
+        type Output = u32;
+        type Driver = Weak<SyncDriver<u32>>;
+        fn complete(self, _: &Self::Driver, payload: u32) -> u32 {
+            payload
+        }
+        fn cancel(self, _: &Self::Driver) -> SendCancellation {
+            SendCancellation::recycle_send(self)
+        }
+    }
+
+    fn block_on<F: Future>(fut: F) -> F::Output {
+        struct Unpark(Thread);
+        impl Wake for Unpark {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark()
+            }
+        }
+        let waker = Waker::from(Arc::new(Unpark(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut fut = pin!(fut);
+        loop {
+            if let Poll::Ready(t) = fut.as_mut().poll(&mut cx) {
+                return t;
+            }
+            thread::park();
+        }
+    }
+
+    #[test]
+    fn sync_driver_threaded() {
+        fn assert_send<T: Send>(_: &T) {}
+
+        let drv = Arc::new(SyncDriver::<u32>::with_shards(4, 0));
+        let (tx, rx) = mpsc::channel::<(OpId, u32)>();
+        let completer = thread::spawn({
+            let drv = drv.clone();
+            move || {
+                for (id, payload) in rx {
+                    drv.complete(id, payload).unwrap();
+                }
+            }
+        });
+        let tasks = (0..4)
+            .map(|t| {
+                let drv = drv.clone();
+                let tx = tx.clone();
+                thread::spawn(move || {
+                    for i in 0..100 {
+                        let id = drv.submit();
+                        let op = Op::new(Arc::downgrade(&drv), id, Echo);
+                        assert_send(&op);
+                        tx.send((id, t * 100 + i)).unwrap();
+                        assert_eq!(block_on(op), t * 100 + i);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        drop(tx);
+        tasks.into_iter().for_each(|t| t.join().unwrap());
+        completer.join().unwrap();
+        assert!(drv.is_empty());
+    }
+
+    #[test]
+    fn sync_driver_shards() {
+        let drv = Arc::new(SyncDriver::<u32>::with_shards(4, 3));
+        let ids = (0..3)
+            .map(|_| drv.try_submit().unwrap())
+            .collect::<Vec<_>>();
+        assert!(drv.try_submit().is_err());
+        assert_eq!(drv.len(), 3);
+
+        drop(Op::new(Arc::downgrade(&drv), ids[0], Echo));
+        assert!(drv.contains(ids[0]));
+        assert_eq!(
+            drv.complete(ids[0], 0),
+            Err(CompleteError::Cancelled(0, ()))
+        );
+        assert_eq!(drv.complete_all(|_| 1), 2);
+        for &id in &ids[1..] {
+            assert_eq!(block_on(Op::new(Arc::downgrade(&drv), id, Echo)), 1);
+        }
+        assert!(drv.is_empty());
+    }
+
+    #[test]
+    fn sync_driver_stream() {
+        struct Count;
+        unsafe impl Multishot for Count {
+            type Item = u32;
+            type Driver = Weak<SyncDriver<u32>>;
+            fn next(&mut self, _: &Self::Driver, payload: u32) -> u32 {
+                payload
+            }
+            fn cancel(self, _: &Self::Driver) -> SendCancellation {
+                SendCancellation::noop()
+            }
+        }
+
+        let drv = Arc::new(SyncDriver::<u32>::with_shards(2, 0));
+        for _ in 0..100 {
+            let id = drv.submit();
+            let mut stream = OpStream::new(Arc::downgrade(&drv), id, Count);
+            // Payloads may arrive between any two polls of the stream.
+            let completer = thread::spawn({
+                let drv = drv.clone();
+                move || {
+                    (0..4).for_each(|i| drv.complete_more(id, i).unwrap());
+                    drv.complete(id, 4).unwrap();
+                }
+            });
+            let items = block_on(async {
+                let mut items = Vec::new();
+                while let Some(i) = stream.next().await {
+                    items.push(i);
+                }
+                items
+            });
+            assert_eq!(items, [0, 1, 2, 3, 4]);
+            completer.join().unwrap();
+        }
+        assert!(drv.is_empty());
+    }
+
+    #[test]
+    fn sync_driver_shutdown() {
+        let drv = Arc::new(SyncDriver::<u32>::with_shards(4, 0));
+        let ids = (0..8).map(|_| drv.submit()).collect::<Vec<_>>();
+        ids.iter()
+            .for_each(|&id| drop(Op::new(Arc::downgrade(&drv), id, Echo)));
+        let completer = thread::spawn({
+            let drv = drv.clone();
+            move || ids.into_iter().for_each(|id| _ = drv.complete(id, 0))
+        });
+        assert_eq!(block_on(drv.shutdown(None)), Ok(()));
+        completer.join().unwrap();
+        assert!(drv.is_empty() && drv.force_close().is_empty());
+    }
+}
@@ -1,11 +1,16 @@
 #![doc = include_str!("lib.md")]
 #![allow(clippy::type_complexity)]
+#![feature(async_iterator)]
 #![feature(local_waker)]
-#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
 
+#[cfg(feature = "alloc")]
 extern crate alloc;
 
+#[cfg(feature = "alloc")]
 pub mod driver;
+#[cfg(feature = "alloc")]
 pub mod op;
+#[cfg(feature = "alloc")]
 pub mod resource;
 pub mod uring;
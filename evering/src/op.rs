@@ -2,9 +2,12 @@
 
 use alloc::boxed::Box;
 use core::any::Any;
+use core::async_iter::AsyncIterator;
 use core::pin::Pin;
 use core::task::{Context, Poll};
+use core::time::Duration;
 
+use crate::driver::private::Sealed as _;
 use crate::driver::{DriverHandle, OpId};
 
 /// # Safety
@@ -41,18 +44,102 @@ pub unsafe trait Completable: 'static + Unpin {
     }
 
     /// Cancels this operation.
-    fn cancel(self, driver: &Self::Driver) -> Cancellation;
+    fn cancel(self, driver: &Self::Driver) -> <Self::Driver as DriverHandle>::Cancellation;
 }
 
-pub struct Cancellation(#[allow(dead_code)] Option<Box<dyn Any>>);
+/// An operation which can be resolved before completion once its deadline
+/// elapses, see [`Op::with_deadline`].
+pub trait Expirable: Completable {
+    /// Returns the output of an operation whose deadline elapsed.
+    ///
+    /// Submitted resources are taken by [`cancel`](Completable::cancel), and
+    /// recycled once the late completion arrives.
+    fn expired(driver: &Self::Driver) -> Self::Output;
+}
+
+/// An operation which yields many payloads before it is completed, see
+/// [`OpStream`].
+///
+/// # Safety
+///
+/// All submitted resources must be recycled.
+pub unsafe trait Multishot: 'static + Unpin {
+    type Item;
+    type Driver: DriverHandle;
+
+    /// Transforms a received payload to the corresponding item, including the
+    /// terminal one.
+    fn next(
+        &mut self,
+        driver: &Self::Driver,
+        payload: <Self::Driver as DriverHandle>::Payload,
+    ) -> Self::Item;
+
+    /// Cancels this operation.
+    fn cancel(self, driver: &Self::Driver) -> <Self::Driver as DriverHandle>::Cancellation;
+}
+
+/// Resources of a cancelled operation, which are kept until the operation is
+/// completed.
+pub struct Cancellation<R: ?Sized = dyn Any> {
+    resource: Option<Box<R>>,
+    remote: bool,
+}
+
+/// A [`Cancellation`] whose resources may be recycled on another thread, which
+/// is required by `SyncDriver`.
+pub type SendCancellation = Cancellation<dyn Any + Send>;
 
 impl Cancellation {
+    pub fn recycle<T: 'static>(resource: T) -> Self {
+        Self {
+            resource: Some(Box::new(resource)),
+            remote: false,
+        }
+    }
+}
+
+impl SendCancellation {
+    /// Like [`recycle`](Cancellation::recycle), but the resource may be
+    /// recycled on another thread.
+    pub fn recycle_send<T: Send + 'static>(resource: T) -> Self {
+        Self {
+            resource: Some(Box::new(resource)),
+            remote: false,
+        }
+    }
+}
+
+impl<R: ?Sized> Cancellation<R> {
     pub const fn noop() -> Self {
-        Self(None)
+        Self {
+            resource: None,
+            remote: false,
+        }
     }
 
-    pub fn recycle<T: 'static>(resource: T) -> Self {
-        Self(Some(Box::new(resource)))
+    /// Asks the responder to cancel the operation as well, see
+    /// [`Driver::take_cancel`].
+    ///
+    /// Resources are still recycled once the operation is completed, which is
+    /// expected to come earlier since the responder may stop working on it.
+    ///
+    /// [`Driver::take_cancel`]: crate::driver::Driver::take_cancel
+    pub const fn remote(mut self) -> Self {
+        self.remote = true;
+        self
+    }
+
+    /// Takes the resource to be recycled, e.g. one handed back by
+    /// [`Driver::force_close`] which will never be completed.
+    ///
+    /// [`Driver::force_close`]: crate::driver::Driver::force_close
+    pub fn into_resource(self) -> Option<Box<R>> {
+        self.resource
+    }
+
+    pub(crate) const fn is_remote(&self) -> bool {
+        self.remote
     }
 }
 
@@ -60,6 +147,7 @@ pub struct Op<T: Completable> {
     driver: T::Driver,
     id: OpId,
     data: Option<T>,
+    expired: Option<fn(&T::Driver) -> T::Output>,
 }
 
 impl<T: Completable> Op<T> {
@@ -68,25 +156,52 @@ impl<T: Completable> Op<T> {
             driver,
             id,
             data: Some(data),
+            expired: None,
         }
     }
+
+    /// Creates an [`Op`] which is resolved by [`Expirable::expired`] if it is
+    /// not completed before [`Driver::expire`] reaches `deadline`.
+    ///
+    /// [`Driver::expire`]: crate::driver::Driver::expire
+    pub fn with_deadline(driver: T::Driver, id: OpId, data: T, deadline: Duration) -> Self
+    where
+        T: Expirable,
+    {
+        driver.get().set_deadline(id, deadline);
+        let mut op = Self::new(driver, id, data);
+        op.expired = Some(T::expired);
+        op
+    }
 }
 
 impl<T: Completable> Future for Op<T> {
     type Output = T::Output;
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        self.driver.get().poll(self.id, cx).map(|(p, ext)| {
-            self.data
-                .take()
-                .expect("invalid operation state")
-                .complete_ext(&self.driver, p, ext)
+        let this = &mut *self;
+        let res = core::task::ready!(this.driver.get().poll(this.id, cx));
+        let data = this.data.take().expect("invalid operation state");
+        Poll::Ready(match res {
+            Ok((p, ext)) => data.complete_ext(&this.driver, p, ext),
+            Err(_) => {
+                let expired = this.expired.expect("invalid operation state");
+                let mut data = Some(data);
+                this.driver
+                    .get()
+                    .remove(this.id, &mut || data.take().unwrap().cancel(&this.driver));
+                expired(&this.driver)
+            },
         })
     }
 }
 
 impl<T: Completable> Drop for Op<T> {
     fn drop(&mut self) {
-        self.driver.get().remove(self.id, || {
+        // Resolved operations are already removed or cancelled.
+        if self.data.is_none() {
+            return;
+        }
+        self.driver.get().remove(self.id, &mut || {
             self.data
                 .take()
                 .expect("invalid operation state")
@@ -94,3 +209,152 @@ impl<T: Completable> Drop for Op<T> {
         })
     }
 }
+
+/// A linked chain of operations, which resolves with the outputs of all of
+/// them, or the first error in the order of the chain.
+///
+/// Entries of the chain should be sent by
+/// [`Uring::send_chain`](crate::uring::Uring::send_chain), so that the
+/// responder does not start an operation if a previous one fails. Dropping a
+/// [`Chain`] cancels all of its links that have not been completed.
+pub struct Chain<L: Links> {
+    ops: L,
+    outputs: L::Outputs,
+}
+
+impl<L: Links> Chain<L> {
+    /// Creates a chain from a tuple of [`Op`]s.
+    pub fn new(ops: L) -> Self {
+        Self {
+            ops,
+            outputs: L::Outputs::default(),
+        }
+    }
+}
+
+// Outputs are never pinned.
+impl<L: Links> Unpin for Chain<L> {}
+
+impl<L: Links> Future for Chain<L> {
+    type Output = L::Output;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        this.ops.poll_links(&mut this.outputs, cx)
+    }
+}
+
+/// Tuples of [`Op`]s which form a [`Chain`], whose outputs are [`Result`]s of
+/// the same error type.
+pub trait Links: Unpin {
+    type Outputs: Default;
+    type Output;
+
+    fn poll_links(&mut self, outputs: &mut Self::Outputs, cx: &mut Context) -> Poll<Self::Output>;
+}
+
+macro_rules! impl_links {
+    ($($T:ident $O:ident $i:tt),+) => {
+        impl<E, $($T, $O),+> Links for ($(Op<$T>,)+)
+        where
+            $($T: Completable<Output = Result<$O, E>>,)+
+        {
+            type Outputs = ($(Option<Result<$O, E>>,)+);
+            type Output = Result<($($O,)+), E>;
+
+            fn poll_links(
+                &mut self,
+                outputs: &mut Self::Outputs,
+                cx: &mut Context,
+            ) -> Poll<Self::Output> {
+                $(
+                    if outputs.$i.is_none() {
+                        if let Poll::Ready(out) = Pin::new(&mut self.$i).poll(cx) {
+                            outputs.$i = Some(out);
+                        }
+                    }
+                )+
+                // Later links may fail because of an earlier one, so wait for
+                // all earlier links before reporting an error.
+                $(
+                    match &outputs.$i {
+                        None => return Poll::Pending,
+                        Some(Err(_)) => {
+                            let Some(Err(e)) = outputs.$i.take() else { unreachable!() };
+                            return Poll::Ready(Err(e));
+                        },
+                        Some(Ok(_)) => {},
+                    }
+                )+
+                Poll::Ready(Ok(($(outputs.$i.take().unwrap().ok().unwrap(),)+)))
+            }
+        }
+    };
+}
+
+impl_links!(A OA 0, B OB 1);
+impl_links!(A OA 0, B OB 1, C OC 2);
+impl_links!(A OA 0, B OB 1, C OC 2, D OD 3);
+impl_links!(A OA 0, B OB 1, C OC 2, D OD 3, F OF 4);
+impl_links!(A OA 0, B OB 1, C OC 2, D OD 3, F OF 4, G OG 5);
+
+/// A multishot operation, which yields an item for every payload delivered by
+/// [`Driver::complete_more`], and ends after the terminal payload delivered by
+/// [`Driver::complete`].
+///
+/// Like [`Op`], it is cancelled through [`Multishot::cancel`] if dropped
+/// before the terminal payload is received.
+///
+/// [`Driver::complete`]: crate::driver::Driver::complete
+/// [`Driver::complete_more`]: crate::driver::Driver::complete_more
+pub struct OpStream<T: Multishot> {
+    driver: T::Driver,
+    id: OpId,
+    data: Option<T>,
+}
+
+impl<T: Multishot> OpStream<T> {
+    pub fn new(driver: T::Driver, id: OpId, data: T) -> Self {
+        Self {
+            driver,
+            id,
+            data: Some(data),
+        }
+    }
+
+    /// Returns the next item, or [`None`] once the terminal payload has been
+    /// received.
+    pub async fn next(&mut self) -> Option<T::Item> {
+        core::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+}
+
+impl<T: Multishot> AsyncIterator for OpStream<T> {
+    type Item = T::Item;
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        let Some(data) = this.data.as_mut() else {
+            return Poll::Ready(None);
+        };
+        let drv = this.driver.get();
+        let (p, last) =
+            core::task::ready!(drv.poll_next(this.id, cx)).expect("invalid operation state");
+        drop(drv);
+        if !last {
+            return Poll::Ready(Some(data.next(&this.driver, p)));
+        }
+        let mut data = this.data.take().unwrap();
+        Poll::Ready(Some(data.next(&this.driver, p)))
+    }
+}
+
+impl<T: Multishot> Drop for OpStream<T> {
+    fn drop(&mut self) {
+        let Some(data) = self.data.take() else {
+            return;
+        };
+        let mut data = Some(data);
+        self.driver
+            .get()
+            .remove(self.id, &mut || data.take().unwrap().cancel(&self.driver))
+    }
+}
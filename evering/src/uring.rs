@@ -1,10 +1,54 @@
 #![doc = include_str!("uring.md")]
 
-use alloc::alloc::Layout;
+mod broadcast;
+#[cfg(all(test, loom, feature = "alloc"))]
+mod model;
+#[cfg(feature = "alloc")]
+mod mpsc;
+#[cfg(feature = "alloc")]
+mod overflow;
+mod peer;
+mod record;
+mod shm;
+#[cfg(not(loom))]
+mod static_uring;
+#[cfg(feature = "stats")]
+mod stats;
+mod sync;
+mod wait;
+
+use core::alloc::Layout;
 use core::fmt;
 use core::marker::PhantomData;
+use core::mem::MaybeUninit;
 use core::ptr::NonNull;
-use core::sync::atomic::{AtomicU32, Ordering};
+
+#[cfg(feature = "alloc")]
+pub use self::broadcast::Broadcast;
+pub use self::broadcast::{
+    BroadcastError, BroadcastHeader, BroadcastLayout, BroadcastReceiver, BroadcastSender,
+    BroadcastSlot, RawBroadcast,
+};
+#[cfg(feature = "alloc")]
+pub use self::mpsc::{MpscSender, UringMpsc};
+#[cfg(feature = "alloc")]
+pub use self::overflow::UringOverflow;
+pub use self::peer::PeerMonitor;
+pub use self::record::{ReadRecord, WriteRecord};
+pub use self::shm::ShmSafe;
+#[cfg(not(loom))]
+pub use self::static_uring::{StaticUring, StaticUringA, StaticUringB};
+#[cfg(feature = "stats")]
+pub use self::stats::{QueueStats, UringStats};
+use self::sync::{AtomicU32, Ordering, const_fn};
+use self::wait::Event;
+#[cfg(all(feature = "futex", target_os = "linux"))]
+pub use self::wait::Futex;
+#[cfg(feature = "std")]
+use self::wait::Park;
+#[cfg(feature = "std")]
+pub use self::wait::Yield;
+pub use self::wait::{Spin, WaitStrategy};
 
 mod private {
     pub trait Sealed {}
@@ -27,6 +71,89 @@ impl fmt::Display for DisposeError {
 
 impl core::error::Error for DisposeError {}
 
+#[non_exhaustive]
+pub struct AttachError {}
+
+impl fmt::Debug for AttachError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AttachError").finish_non_exhaustive()
+    }
+}
+
+impl fmt::Display for AttachError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Uring is not vacant or has no alive remote side")
+    }
+}
+
+impl core::error::Error for AttachError {}
+
+/// An error returned by [`Uring::read_record`] if the record at the front of
+/// the queue is malformed, e.g. the remote side is buggy or sends entries
+/// through [`Uring::send`] instead.
+#[non_exhaustive]
+pub struct RecordError {}
+
+impl fmt::Debug for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RecordError").finish_non_exhaustive()
+    }
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("malformed record in Uring")
+    }
+}
+
+impl core::error::Error for RecordError {}
+
+/// An error returned by [`Header::check_layout`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LayoutError {
+    /// The memory does not start with [`HeaderLayout::MAGIC`].
+    Magic(u32),
+    /// The memory is built in another [`HeaderLayout::VERSION`].
+    Version(u32),
+    /// Size or alignment of a type does not match.
+    Mismatch {
+        name: &'static str,
+        expected: TypeLayout,
+        found: TypeLayout,
+    },
+}
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Magic(magic) => write!(f, "bad magic number {magic:#x} of Uring header"),
+            Self::Version(ver) => write!(
+                f,
+                "unsupported Uring format version {ver}, expected {}",
+                HeaderLayout::VERSION
+            ),
+            Self::Mismatch {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "mismatched layout of {name}, expected size={} align={}, found size={} align={}",
+                expected.size, expected.align, found.size, found.align
+            ),
+        }
+    }
+}
+
+impl core::error::Error for LayoutError {}
+
+/// An entry which can be linked to the next one, see [`Uring::send_chain`].
+pub trait Linked {
+    /// Marks whether the next entry depends on this one.
+    fn set_linked(&mut self, linked: bool);
+}
+
 pub trait Uring: private::Sealed {
     type A;
     type B;
@@ -34,9 +161,26 @@ pub trait Uring: private::Sealed {
 
     fn header(&self) -> &Header<Self::Ext>;
 
-    fn sender(&self) -> Queue<Self::A>;
+    /// Returns the sending queue of the given lane, or [`None`] if there is no
+    /// such lane.
+    ///
+    /// Lanes are ordered by priority, where lane `0` has the highest priority
+    /// and is used by [`send`](Self::send).
+    fn sender_lane(&self, lane: usize) -> Option<Queue<Self::A>>;
+
+    /// Returns the receiving queue of the given lane, or [`None`] if there is
+    /// no such lane.
+    fn receiver_lane(&self, lane: usize) -> Option<Queue<Self::B>>;
 
-    fn receiver(&self) -> Queue<Self::B>;
+    /// Returns the sending queue of lane `0`.
+    fn sender(&self) -> Queue<Self::A> {
+        self.sender_lane(0).expect("lane 0 always exists")
+    }
+
+    /// Returns the receiving queue of lane `0`.
+    fn receiver(&self) -> Queue<Self::B> {
+        self.receiver_lane(0).expect("lane 0 always exists")
+    }
 
     fn ext(&self) -> &Self::Ext
     where
@@ -47,7 +191,51 @@ pub trait Uring: private::Sealed {
 
     /// Returns `true` if the remote [`Uring`] is not dropped.
     fn is_connected(&self) -> bool {
-        self.header().rc.load(Ordering::Relaxed) > 1
+        // Pairs with `Header::close`, so that entries sent before the remote
+        // side closes are visible once it is seen as disconnected.
+        self.header().closed.load(Ordering::Acquire) == 0
+    }
+
+    /// Marks the connection as closed, and wakes up all blocked waiters.
+    ///
+    /// This is useful to tear down a connection whose remote side died without
+    /// dropping its [`Uring`]. Sending and receiving are still allowed.
+    fn close(&self) {
+        self.header().close();
+    }
+
+    /// Announces that this side is still alive.
+    ///
+    /// See [`PeerMonitor`] for more information.
+    fn heartbeat(&self) {
+        self.sender().off.prod.beat.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the session epoch, which is increased every time a new remote
+    /// side attaches to this [`Uring`].
+    ///
+    /// Entries sent by the previous remote side are kept in the receiving
+    /// queue, while entries sent to it are discarded.
+    fn session(&self) -> u32 {
+        self.header().epoch.load(Ordering::Acquire)
+    }
+
+    /// Returns the number of heartbeats sent by the remote side.
+    fn peer_heartbeat(&self) -> u32 {
+        self.receiver().off.prod.beat.load(Ordering::Relaxed)
+    }
+
+    /// Returns `true` if the remote side has entries waiting in its overflow
+    /// list, see [`UringOverflow`].
+    fn peer_overflowed(&self) -> bool {
+        self.receiver().off.prod.overflow.load(Ordering::Relaxed) != 0
+    }
+
+    /// Returns `true` if the remote side sleeps in
+    /// [`recv_polling`](Self::recv_polling), in which case the next send
+    /// wakes it up.
+    fn peer_needs_wakeup(&self) -> bool {
+        self.sender().ev.readable.need_wakeup()
     }
 
     fn send(&mut self, val: Self::A) -> Result<(), Self::A> {
@@ -61,12 +249,282 @@ pub trait Uring: private::Sealed {
         unsafe { self.sender().enqueue_bulk(vals) }
     }
 
+    /// Reserves at most `n` slots in the sending queue.
+    ///
+    /// Entries written to the returned [`Reserve`] are published all at once on
+    /// [`commit`](Reserve::commit), and nothing is published if it is dropped
+    /// without committing.
+    fn reserve(&mut self, n: usize) -> Reserve<Self::A> {
+        unsafe { self.sender().reserve(n, false) }
+    }
+
+    /// Sends all entries at once as a linked chain, or returns them back if
+    /// there are not enough free slots.
+    ///
+    /// Every entry but the last one is marked by [`Linked::set_linked`], which
+    /// tells the responder that the next entry depends on it. The responder
+    /// should process them in order, and fail the rest of the chain once an
+    /// entry fails. Since the chain is published at once, the responder never
+    /// sees a partial chain.
+    fn send_chain<I>(&mut self, entries: I) -> Result<(), I::IntoIter>
+    where
+        I: IntoIterator<Item = Self::A>,
+        I::IntoIter: ExactSizeIterator,
+        Self::A: Linked,
+    {
+        let mut entries = entries.into_iter();
+        let n = entries.len();
+        let mut batch = self.reserve(n);
+        if batch.capacity() < n {
+            return Err(entries);
+        }
+        for (i, mut val) in entries.by_ref().enumerate() {
+            val.set_linked(i + 1 < n);
+            _ = batch.push(val);
+        }
+        batch.commit();
+        Ok(())
+    }
+
+    /// Reserves a contiguous record of `len` bytes in lane `0` of a byte
+    /// [`Uring`], or returns [`None`] if there is not enough space.
+    ///
+    /// Records are length-prefixed, so that messages of different sizes can
+    /// be carried inline. The record is published on
+    /// [`commit`](WriteRecord::commit). A byte [`Uring`] should not be used
+    /// with [`send`](Self::send) at the same time, and holds no record if its
+    /// buffer is smaller than 8 bytes.
+    fn write_record(&mut self, len: usize) -> Option<WriteRecord>
+    where
+        Self: Uring<A = u8>,
+    {
+        unsafe { self.sender().write_record(len, false) }
+    }
+
+    /// Sends an entry to the given lane.
+    ///
+    /// Entries sent to lane `0` go through [`send`](Self::send).
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no such lane.
+    fn send_to(&mut self, lane: usize, val: Self::A) -> Result<(), Self::A> {
+        if lane == 0 {
+            return self.send(val);
+        }
+        let mut queue = self.sender_lane(lane).expect("lane out of range");
+        unsafe { queue.enqueue(val) }
+    }
+
+    /// Sends entries to the given lane in a batch.
+    ///
+    /// Entries sent to lane `0` go through [`send_bulk`](Self::send_bulk).
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no such lane.
+    fn send_bulk_to<I>(&mut self, lane: usize, vals: I) -> usize
+    where
+        I: Iterator<Item = Self::A>,
+    {
+        if lane == 0 {
+            return self.send_bulk(vals);
+        }
+        let mut queue = self.sender_lane(lane).expect("lane out of range");
+        unsafe { queue.enqueue_bulk(vals) }
+    }
+
+    /// Returns a snapshot of traffic counters in both directions.
+    #[cfg(feature = "stats")]
+    fn stats(&self) -> UringStats {
+        UringStats {
+            sender: QueueStats::collect((0..).map_while(|lane| self.sender_lane(lane))),
+            receiver: QueueStats::collect((0..).map_while(|lane| self.receiver_lane(lane))),
+        }
+    }
+
+    /// Receives an entry from the non-empty lane of the highest priority.
     fn recv(&mut self) -> Option<Self::B> {
-        unsafe { self.receiver().dequeue() }
+        let mut lane = 0;
+        while let Some(mut queue) = self.receiver_lane(lane) {
+            if let Some(val) = unsafe { queue.dequeue() } {
+                return Some(val);
+            }
+            lane += 1;
+        }
+        self.receiver().off.record_empty();
+        None
     }
 
+    /// Receives all entries of the non-empty lane of the highest priority.
     fn recv_bulk(&mut self) -> Drain<Self::B> {
-        unsafe { self.receiver().dequeue_bulk() }
+        let mut queue = self.receiver();
+        let mut lane = 1;
+        while queue.is_empty() {
+            let Some(next) = self.receiver_lane(lane) else {
+                break;
+            };
+            queue = next;
+            lane += 1;
+        }
+        if queue.is_empty() {
+            queue.off.record_empty();
+        }
+        unsafe { queue.dequeue_bulk() }
+    }
+
+    /// Returns a reference to the entry which [`recv`](Self::recv) would
+    /// return next, without removing it from the queue.
+    fn peek(&mut self) -> Option<&Self::B> {
+        let this = &*self;
+        let mut lane = 0;
+        while let Some(queue) = this.receiver_lane(lane) {
+            if let Some(val) = unsafe { queue.peek() } {
+                return Some(val);
+            }
+            lane += 1;
+        }
+        None
+    }
+
+    /// Receives an entry like [`recv`](Self::recv), but leaves it in the queue
+    /// until the returned [`RecvGuard`] is dropped or accepted.
+    fn recv_guard(&mut self) -> Option<RecvGuard<Self::B>> {
+        let this = &*self;
+        let mut lane = 0;
+        while let Some(queue) = this.receiver_lane(lane) {
+            if let Some(guard) = unsafe { queue.recv_guard() } {
+                return Some(guard);
+            }
+            lane += 1;
+        }
+        this.receiver().off.record_empty();
+        None
+    }
+
+    /// Reads the next record from lane `0` of a byte [`Uring`], which is
+    /// consumed once the returned [`ReadRecord`] is dropped.
+    ///
+    /// A malformed record is left in the queue, and a [`RecordError`] is
+    /// returned instead.
+    fn read_record(&mut self) -> Result<Option<ReadRecord>, RecordError>
+    where
+        Self: Uring<B = u8>,
+    {
+        let this = &*self;
+        let record = unsafe { this.receiver().read_record() };
+        if let Ok(None) = record {
+            this.receiver().off.record_empty();
+        }
+        record
+    }
+
+    /// Receives an entry from the given lane only.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no such lane.
+    fn recv_from(&mut self, lane: usize) -> Option<Self::B> {
+        let mut queue = self.receiver_lane(lane).expect("lane out of range");
+        let val = unsafe { queue.dequeue() };
+        if val.is_none() {
+            queue.off.record_empty();
+        }
+        val
+    }
+
+    /// Receives all entries of the given lane only.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no such lane.
+    fn recv_bulk_from(&mut self, lane: usize) -> Drain<Self::B> {
+        let mut queue = self.receiver_lane(lane).expect("lane out of range");
+        unsafe { queue.dequeue_bulk() }
+    }
+
+    /// Sends an entry, waiting for free slots with the given strategy if the
+    /// sending queue is full.
+    ///
+    /// It returns the entry as an [`Err`] if the remote side is disconnected.
+    fn send_blocking<W: WaitStrategy>(&mut self, val: Self::A, wait: &W) -> Result<(), Self::A> {
+        let mut val = Some(val);
+        wait::wait_for(
+            self,
+            wait,
+            |u| &u.sender().ev.writable,
+            |u| match u.send(val.take().unwrap()) {
+                Ok(()) => Some(()),
+                Err(v) => {
+                    val = Some(v);
+                    None
+                },
+            },
+            || None,
+        )
+        .ok_or_else(|| val.unwrap())
+    }
+
+    /// Receives an entry, waiting for new entries with the given strategy if
+    /// the receiving queue is empty.
+    ///
+    /// It returns [`None`] if the remote side is disconnected.
+    fn recv_blocking<W: WaitStrategy>(&mut self, wait: &W) -> Option<Self::B> {
+        wait::wait_for(
+            self,
+            wait,
+            |u| &u.receiver().ev.readable,
+            Self::recv,
+            || None,
+        )
+    }
+
+    /// Receives an entry like [`recv_blocking`](Self::recv_blocking), but
+    /// waits for at most `timeout`.
+    #[cfg(feature = "std")]
+    fn recv_timeout<W: WaitStrategy>(
+        &mut self,
+        wait: &W,
+        timeout: core::time::Duration,
+    ) -> Option<Self::B> {
+        let deadline = std::time::Instant::now() + timeout;
+        wait::wait_for(
+            self,
+            wait,
+            |u| &u.receiver().ev.readable,
+            Self::recv,
+            || Some(deadline.saturating_duration_since(std::time::Instant::now())),
+        )
+    }
+
+    /// Receives an entry like [`recv_timeout`](Self::recv_timeout), but
+    /// busy-polls the receiving queue for `idle` before going to sleep.
+    ///
+    /// This resembles the SQPOLL mode of io_uring. Only while sleeping is the
+    /// receiving queue marked as [needing wakeup](Self::peer_needs_wakeup), so
+    /// sending to a busy receiver never issues any syscall.
+    #[cfg(feature = "std")]
+    fn recv_polling<W: WaitStrategy>(
+        &mut self,
+        wait: &W,
+        idle: core::time::Duration,
+        timeout: core::time::Duration,
+    ) -> Option<Self::B> {
+        let start = std::time::Instant::now();
+        // Unlike `recv`, `peek` does not count empty polls.
+        while self.peek().is_none() && start.elapsed() < idle.min(timeout) {
+            sync::spin_loop();
+        }
+        let deadline = start + timeout;
+        wait::park_for(
+            self,
+            wait,
+            |u| &u.receiver().ev.readable,
+            |u| u.is_connected(),
+            Park::NeedWakeup,
+            Self::recv,
+            || Some(deadline.saturating_duration_since(std::time::Instant::now())),
+        )
     }
 }
 
@@ -88,17 +546,17 @@ impl<T, Ext> Uring for UringEither<T, Ext> {
         }
     }
 
-    fn sender(&self) -> Queue<T> {
+    fn sender_lane(&self, lane: usize) -> Option<Queue<T>> {
         match self {
-            UringEither::A(a) => a.sender(),
-            UringEither::B(b) => b.sender(),
+            UringEither::A(a) => a.sender_lane(lane),
+            UringEither::B(b) => b.sender_lane(lane),
         }
     }
 
-    fn receiver(&self) -> Queue<T> {
+    fn receiver_lane(&self, lane: usize) -> Option<Queue<T>> {
         match self {
-            UringEither::A(a) => a.receiver(),
-            UringEither::B(b) => b.receiver(),
+            UringEither::A(a) => a.receiver_lane(lane),
+            UringEither::B(b) => b.receiver_lane(lane),
         }
     }
 }
@@ -113,7 +571,7 @@ unsafe impl<A: Send, B: Send, Ext: Send> Send for UringA<A, B, Ext> {}
 unsafe impl<A: Send, B: Send, Ext: Send> Send for UringB<A, B, Ext> {}
 
 macro_rules! common_methods {
-    ($A:ident, $B:ident, $Ext:ident) => {
+    ($A:ident, $B:ident, $Ext:ident, $side:ident) => {
         pub fn into_raw(self) -> RawUring<A, B, Ext> {
             let inner = RawUring {
                 header: self.0.header,
@@ -133,13 +591,59 @@ macro_rules! common_methods {
         pub fn dispose_raw(self) -> Result<RawUring<A, B, Ext>, DisposeError> {
             let mut raw = self.into_raw();
             unsafe {
-                match raw.dispose() {
+                match raw.dispose($side) {
                     Ok(_) => Ok(raw),
                     Err(e) => Err(e),
                 }
             }
         }
 
+        /// Drops this [`Uring`] and all enqueued entries regardless of whether
+        /// the remote side is alive.
+        ///
+        /// # Safety
+        ///
+        /// The remote side must never access this [`Uring`] again, e.g. it has
+        /// been reported dead by a [`PeerMonitor`].
+        pub unsafe fn force_dispose_raw(self) -> RawUring<A, B, Ext> {
+            let mut raw = self.into_raw();
+            unsafe { raw.force_dispose() };
+            raw
+        }
+
+        /// Attaches to this side of a connection whose previous owner is
+        /// gone, while the remote side is still alive.
+        ///
+        /// Entries sent to the previous owner are discarded, and the remote
+        /// side can find the new session through [`Uring::session`]. It
+        /// returns an error if this side is still occupied, or if the remote
+        /// side is gone as well.
+        ///
+        /// # Safety
+        ///
+        /// The specified [`RawUring`] must point to a valid [`Uring`], e.g. one
+        /// built from [`into_raw`](Self::into_raw).
+        pub unsafe fn attach(uring: RawUring<A, B, Ext>) -> Result<Self, AttachError> {
+            let mut raw = uring;
+            unsafe { raw.attach($side)? };
+            Ok(Self(raw))
+        }
+
+        /// Marks the remote side as gone, so that a new one can
+        /// [`attach`](Self::attach) to it.
+        ///
+        /// This also closes the connection like [`Uring::close`].
+        ///
+        /// # Safety
+        ///
+        /// The remote side must never access this [`Uring`] again, e.g. it has
+        /// been reported dead by a [`PeerMonitor`].
+        pub unsafe fn detach_peer(&self) {
+            let h = unsafe { self.0.header() };
+            h.close();
+            h.attached.fetch_and($side, Ordering::Release);
+        }
+
         /// # Safety
         ///
         /// The specified [`RawUring`] must be a valid value returned from
@@ -151,11 +655,11 @@ macro_rules! common_methods {
 }
 
 impl<A, B, Ext> UringA<A, B, Ext> {
-    common_methods!(A, B, Ext);
+    common_methods!(A, B, Ext, SIDE_A);
 }
 
 impl<A, B, Ext> UringB<A, B, Ext> {
-    common_methods!(A, B, Ext);
+    common_methods!(A, B, Ext, SIDE_B);
 }
 
 impl<A, B, Ext> private::Sealed for UringA<A, B, Ext> {}
@@ -167,11 +671,11 @@ impl<A, B, Ext> Uring for UringA<A, B, Ext> {
     fn header(&self) -> &Header<Ext> {
         unsafe { self.0.header() }
     }
-    fn sender(&self) -> Queue<Self::A> {
-        unsafe { self.0.queue_a() }
+    fn sender_lane(&self, lane: usize) -> Option<Queue<Self::A>> {
+        unsafe { self.0.queue_a(lane) }
     }
-    fn receiver(&self) -> Queue<Self::B> {
-        unsafe { self.0.queue_b() }
+    fn receiver_lane(&self, lane: usize) -> Option<Queue<Self::B>> {
+        unsafe { self.0.queue_b(lane) }
     }
 }
 
@@ -184,62 +688,406 @@ impl<A, B, Ext> Uring for UringB<A, B, Ext> {
     fn header(&self) -> &Header<Ext> {
         unsafe { self.0.header() }
     }
-    fn sender(&self) -> Queue<Self::A> {
-        unsafe { self.0.queue_b() }
+    fn sender_lane(&self, lane: usize) -> Option<Queue<Self::A>> {
+        unsafe { self.0.queue_b(lane) }
     }
-    fn receiver(&self) -> Queue<Self::B> {
-        unsafe { self.0.queue_a() }
+    fn receiver_lane(&self, lane: usize) -> Option<Queue<Self::B>> {
+        unsafe { self.0.queue_a(lane) }
     }
 }
 
 impl<A, B, Ext> Drop for UringA<A, B, Ext> {
     fn drop(&mut self) {
-        unsafe { self.0.drop_in_place() }
+        unsafe { self.0.drop_in_place(SIDE_A) }
     }
 }
 
 impl<A, B, Ext> Drop for UringB<A, B, Ext> {
     fn drop(&mut self) {
-        unsafe { self.0.drop_in_place() }
+        unsafe { self.0.drop_in_place(SIDE_B) }
+    }
+}
+
+/// The on-memory format of a [`Uring`].
+///
+/// It is placed at the start of [`Header`] and never changes its own layout, so
+/// that a [`Header`] built by another program can always be validated before it
+/// is used.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HeaderLayout {
+    pub magic: u32,
+    pub version: u32,
+    pub header: TypeLayout,
+    pub a: TypeLayout,
+    pub b: TypeLayout,
+    pub ext: TypeLayout,
+}
+
+impl HeaderLayout {
+    pub const MAGIC: u32 = u32::from_be_bytes(*b"EVRG");
+    /// Bumped whenever the layout of [`Header`] or [`BroadcastHeader`]
+    /// changes.
+    pub const VERSION: u32 = 9;
+
+    pub const fn new<A, B, Ext>() -> Self {
+        Self {
+            magic: Self::MAGIC,
+            version: Self::VERSION,
+            header: TypeLayout::of::<Header<Ext>>(),
+            a: TypeLayout::of::<A>(),
+            b: TypeLayout::of::<B>(),
+            ext: TypeLayout::of::<Ext>(),
+        }
+    }
+}
+
+/// Size and alignment of a type.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TypeLayout {
+    pub size: u32,
+    pub align: u32,
+}
+
+impl TypeLayout {
+    pub const fn of<T>() -> Self {
+        Self {
+            size: size_of::<T>() as u32,
+            align: align_of::<T>() as u32,
+        }
     }
 }
 
+/// The maximum number of lanes in each direction.
+pub const MAX_LANES: usize = 4;
+
+#[repr(C)]
 pub struct Header<Ext = ()> {
-    off_a: Offsets,
-    off_b: Offsets,
-    rc: AtomicU32,
+    layout: HeaderLayout,
+    off_a: [Offsets; MAX_LANES],
+    off_b: [Offsets; MAX_LANES],
+    ev_a: Events,
+    ev_b: Events,
+    lanes_a: u32,
+    lanes_b: u32,
+    /// Sides that are currently attached, see [`SIDE_A`] and [`SIDE_B`].
+    attached: AtomicU32,
+    /// Set once either side starts to drop, before it gives up its reference.
+    closed: AtomicU32,
+    /// Increased every time a new side attaches.
+    epoch: AtomicU32,
+    /// Set if the memory is allocated by [`Builder::build`], which is released
+    /// along with the last side. Otherwise, it is never deallocated on drop.
+    #[cfg_attr(not(feature = "alloc"), allow(dead_code))]
+    allocated: u32,
     ext: Ext,
 }
 
+const SIDE_A: u32 = 1 << 0;
+const SIDE_B: u32 = 1 << 1;
+
 impl<Ext> Header<Ext> {
+    pub fn layout(&self) -> &HeaderLayout {
+        &self.layout
+    }
+
+    /// Checks whether this [`Header`] is built for a [`Uring`] of the given
+    /// types in the current format.
+    ///
+    /// This should be called before building a [`RawUring`] from memory
+    /// initialized by another program.
+    pub fn check_layout<A, B>(&self) -> Result<(), LayoutError> {
+        let found = &self.layout;
+        if found.magic != HeaderLayout::MAGIC {
+            return Err(LayoutError::Magic(found.magic));
+        }
+        if found.version != HeaderLayout::VERSION {
+            return Err(LayoutError::Version(found.version));
+        }
+        let expected = HeaderLayout::new::<A, B, Ext>();
+        for (name, expected, found) in [
+            ("Header", expected.header, found.header),
+            ("A", expected.a, found.a),
+            ("B", expected.b, found.b),
+            ("Ext", expected.ext, found.ext),
+        ] {
+            if expected != found {
+                return Err(LayoutError::Mismatch {
+                    name,
+                    expected,
+                    found,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the total size of all lanes of the A side.
     pub fn size_a(&self) -> usize {
-        self.off_a.ring_mask as usize + 1
+        self.queues_a().iter().map(Offsets::size).sum()
     }
 
+    /// Returns the total size of all lanes of the B side.
     pub fn size_b(&self) -> usize {
-        self.off_b.ring_mask as usize + 1
+        self.queues_b().iter().map(Offsets::size).sum()
+    }
+
+    pub fn lanes_a(&self) -> usize {
+        self.queues_a().len()
+    }
+
+    pub fn lanes_b(&self) -> usize {
+        self.queues_b().len()
+    }
+
+    pub fn lane_size_a(&self, lane: usize) -> Option<usize> {
+        self.queues_a().get(lane).map(Offsets::size)
+    }
+
+    pub fn lane_size_b(&self, lane: usize) -> Option<usize> {
+        self.queues_b().get(lane).map(Offsets::size)
+    }
+
+    fn queues_a(&self) -> &[Offsets] {
+        &self.off_a[..(self.lanes_a as usize).min(MAX_LANES)]
+    }
+
+    fn queues_b(&self) -> &[Offsets] {
+        &self.off_b[..(self.lanes_b as usize).min(MAX_LANES)]
+    }
+
+    /// Returns the session epoch, see [`Uring::session`].
+    pub fn session(&self) -> u32 {
+        self.epoch.load(Ordering::Acquire)
+    }
+
+    /// Creates a [`Header`] with a single lane in each direction in const
+    /// contexts, see [`StaticUring`].
+    #[cfg(not(loom))]
+    const fn new_static<A, B>(size_a: u32, size_b: u32, ext: Ext) -> Self {
+        let mut off_a = [const { Offsets::new(1, 0) }; MAX_LANES];
+        let mut off_b = [const { Offsets::new(1, 0) }; MAX_LANES];
+        off_a[0] = Offsets::new(size_a, 0);
+        off_b[0] = Offsets::new(size_b, 0);
+        Self {
+            layout: HeaderLayout::new::<A, B, Ext>(),
+            off_a,
+            off_b,
+            ev_a: Events::new(),
+            ev_b: Events::new(),
+            lanes_a: 1,
+            lanes_b: 1,
+            attached: AtomicU32::new(SIDE_A | SIDE_B),
+            closed: AtomicU32::new(0),
+            epoch: AtomicU32::new(0),
+            allocated: 0,
+            ext,
+        }
+    }
+
+    fn close(&self) {
+        self.closed.store(1, Ordering::Release);
+        self.notify_all();
+    }
+
+    fn notify_all(&self) {
+        for ev in [&self.ev_a, &self.ev_b] {
+            ev.readable.notify();
+            ev.writable.notify();
+        }
     }
 }
 
+/// Indices of a queue.
+///
+/// Indices written by the producer and the consumer are placed on separate
+/// cache lines, so that both sides do not keep invalidating each other's cache.
+/// Each side also keeps a copy of the opposite index, which is refreshed only
+/// when the queue looks full or empty.
+#[repr(C)]
 struct Offsets {
-    head: AtomicU32,
-    tail: AtomicU32,
     ring_mask: u32,
+    /// Index of the first slot of this lane in the shared buffer.
+    start: u32,
+    prod: CachePadded<ProducerOffsets>,
+    cons: CachePadded<ConsumerOffsets>,
 }
 
+#[repr(C)]
+struct ProducerOffsets {
+    tail: AtomicU32,
+    /// Free-running index of the last slot claimed by concurrent producers.
+    /// The highest bit marks that an exclusive producer is active.
+    ///
+    /// It is only meaningful for multi-producer queues.
+    claim: AtomicU32,
+    cached_head: AtomicU32,
+    /// Heartbeats of the producer, see [`PeerMonitor`].
+    beat: AtomicU32,
+    /// Whether the producer has overflowed entries, see [`UringOverflow`].
+    overflow: AtomicU32,
+    #[cfg(feature = "stats")]
+    stats: stats::ProducerStats,
+}
+
+#[repr(C)]
+struct ConsumerOffsets {
+    head: AtomicU32,
+    cached_tail: AtomicU32,
+    #[cfg(feature = "stats")]
+    stats: stats::ConsumerStats,
+}
+
+/// Events shared by all lanes in the same direction, so that a waiter can be
+/// woken up by any of them.
+#[repr(C)]
+struct Events {
+    /// Notified when entries are enqueued.
+    readable: Event,
+    /// Notified when entries are dequeued.
+    writable: Event,
+}
+
+impl Events {
+    const_fn! {
+        fn new() -> Self {
+            Self {
+                readable: Event::new(),
+                writable: Event::new(),
+            }
+        }
+    }
+}
+
+const CLAIM_LOCKED: u32 = 1 << 31;
+
 impl Offsets {
-    fn new(size: u32) -> Self {
-        debug_assert!(size.is_power_of_two());
-        Self {
-            head: AtomicU32::new(0),
-            tail: AtomicU32::new(0),
-            ring_mask: size - 1,
+    const_fn! {
+        fn new(size: u32, start: u32) -> Self {
+            debug_assert!(size.is_power_of_two());
+            debug_assert!(size <= CLAIM_LOCKED);
+            Self {
+                ring_mask: size - 1,
+                start,
+                prod: CachePadded(ProducerOffsets {
+                    tail: AtomicU32::new(0),
+                    claim: AtomicU32::new(0),
+                    cached_head: AtomicU32::new(0),
+                    beat: AtomicU32::new(0),
+                    overflow: AtomicU32::new(0),
+                    #[cfg(feature = "stats")]
+                    stats: stats::ProducerStats::new(),
+                }),
+                cons: CachePadded(ConsumerOffsets {
+                    head: AtomicU32::new(0),
+                    cached_tail: AtomicU32::new(0),
+                    #[cfg(feature = "stats")]
+                    stats: stats::ConsumerStats::new(),
+                }),
+            }
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.ring_mask as usize + 1
+    }
+
+    /// Records `n` entries published up to `tail`, which is a no-op unless the
+    /// `stats` feature is enabled.
+    #[inline]
+    fn record_enqueued(&self, n: u32, tail: u32) {
+        #[cfg(feature = "stats")]
+        {
+            let head = self.cons.head.load(Ordering::Relaxed);
+            let len = tail.wrapping_sub(head) & self.ring_mask;
+            self.prod.stats.enqueued(n, len);
         }
+        _ = (n, tail);
+    }
+
+    #[inline]
+    fn record_full(&self) {
+        #[cfg(feature = "stats")]
+        self.prod.stats.full();
+    }
+
+    #[inline]
+    fn record_dequeued(&self, n: u32) {
+        #[cfg(feature = "stats")]
+        self.cons.stats.dequeued(n);
+        _ = n;
+    }
+
+    #[inline]
+    fn record_empty(&self) {
+        #[cfg(feature = "stats")]
+        self.cons.stats.empty();
     }
 
     fn inc(&self, n: u32) -> u32 {
         n.wrapping_add(1) & self.ring_mask
     }
+
+    /// Returns the number of free slots seen by the exclusive producer,
+    /// refreshing the cached head if there are less than `want` slots.
+    ///
+    /// Since `head` never moves past `tail`, an outdated head can only make the
+    /// queue look fuller than it actually is, as long as it is not outdated by
+    /// a whole lap. Hence the cached head is only used by single producers,
+    /// and refreshed whenever a multi-producer queue is locked.
+    #[inline]
+    fn free(&self, tail: u32, want: u32) -> u32 {
+        let n = self.free_from(tail, self.prod.cached_head.load(Ordering::Relaxed));
+        if n >= want {
+            return n;
+        }
+        self.refresh_head();
+        self.free_from(tail, self.prod.cached_head.load(Ordering::Relaxed))
+    }
+
+    #[inline]
+    fn free_from(&self, tail: u32, head: u32) -> u32 {
+        self.ring_mask - (tail.wrapping_sub(head) & self.ring_mask)
+    }
+
+    #[inline]
+    fn refresh_head(&self) {
+        let head = self.cons.head.load(Ordering::Acquire);
+        self.prod.cached_head.store(head, Ordering::Relaxed);
+    }
+
+    /// Returns the tail seen by the consumer, refreshing the cached tail if the
+    /// queue looks empty.
+    #[inline]
+    fn tail(&self, head: u32) -> u32 {
+        let tail = self.cons.cached_tail.load(Ordering::Relaxed);
+        if tail != head {
+            return tail;
+        }
+        let tail = self.prod.tail.load(Ordering::Acquire);
+        self.cons.cached_tail.store(tail, Ordering::Relaxed);
+        tail
+    }
+}
+
+/// Pads and aligns a value to the length of a cache line.
+///
+/// Credit: <https://docs.rs/crossbeam-utils/latest/crossbeam_utils/struct.CachePadded.html>
+#[repr(C)]
+#[cfg_attr(any(target_arch = "x86_64", target_arch = "aarch64"), repr(align(128)))]
+#[cfg_attr(
+    not(any(target_arch = "x86_64", target_arch = "aarch64")),
+    repr(align(64))
+)]
+struct CachePadded<T>(T);
+
+impl<T> core::ops::Deref for CachePadded<T> {
+    type Target = T;
+    #[inline]
+    fn deref(&self) -> &T {
+        &self.0
+    }
 }
 
 pub struct RawUring<A, B, Ext = ()> {
@@ -263,58 +1111,130 @@ impl<A, B, Ext> RawUring<A, B, Ext> {
         unsafe { self.header.as_ref() }
     }
 
-    unsafe fn queue_a(&self) -> Queue<'_, A> {
-        Queue {
-            off: unsafe { &self.header().off_a },
-            buf: self.buf_a,
-        }
+    unsafe fn queue_a(&self, lane: usize) -> Option<Queue<'_, A>> {
+        let h = unsafe { self.header() };
+        let off = h.queues_a().get(lane)?;
+        Some(Queue {
+            off,
+            ev: &h.ev_a,
+            buf: unsafe { self.buf_a.add(off.start as usize) },
+        })
+    }
+
+    unsafe fn queue_b(&self, lane: usize) -> Option<Queue<'_, B>> {
+        let h = unsafe { self.header() };
+        let off = h.queues_b().get(lane)?;
+        Some(Queue {
+            off,
+            ev: &h.ev_b,
+            buf: unsafe { self.buf_b.add(off.start as usize) },
+        })
+    }
+
+    unsafe fn queues_a(&self) -> impl Iterator<Item = Queue<'_, A>> {
+        (0..).map_while(|lane| unsafe { self.queue_a(lane) })
+    }
+
+    unsafe fn queues_b(&self) -> impl Iterator<Item = Queue<'_, B>> {
+        (0..).map_while(|lane| unsafe { self.queue_b(lane) })
     }
 
-    unsafe fn queue_b(&self) -> Queue<'_, B> {
-        Queue {
-            off: unsafe { &self.header().off_b },
-            buf: self.buf_b,
+    unsafe fn drop_queues(&mut self) {
+        unsafe {
+            self.queues_a().for_each(|mut q| q.drop_in_place());
+            self.queues_b().for_each(|mut q| q.drop_in_place());
         }
     }
 
-    unsafe fn dispose(&mut self) -> Result<(), DisposeError> {
-        let rc = unsafe { &self.header().rc };
-        debug_assert!(rc.load(Ordering::Relaxed) >= 1);
+    unsafe fn dispose(&mut self, side: u32) -> Result<(), DisposeError> {
+        let h = unsafe { self.header() };
+        // Wake up the remote side, which may be waiting for us, while the
+        // header is still guaranteed to be alive.
+        h.close();
+
         // `Release` enforeces any use of the data to happen before here.
-        if rc.fetch_sub(1, Ordering::Release) != 1 {
+        let attached = h.attached.fetch_and(!side, Ordering::Release);
+        debug_assert!(attached & side != 0);
+        if attached != side {
             return Err(DisposeError {});
         }
         // `Acquire` enforces the deletion of the data to happen after here.
-        core::sync::atomic::fence(Ordering::Acquire);
+        sync::fence(Ordering::Acquire);
+
+        unsafe { self.drop_queues() };
+        Ok(())
+    }
+
+    unsafe fn force_dispose(&mut self) {
+        let h = unsafe { self.header() };
+        h.close();
+        h.attached.store(0, Ordering::Relaxed);
+        sync::fence(Ordering::Acquire);
+        unsafe { self.drop_queues() };
+    }
 
+    unsafe fn attach(&mut self, side: u32) -> Result<(), AttachError> {
+        let h = unsafe { self.header() };
+        let remote = (SIDE_A | SIDE_B) & !side;
+        // Only a vacant side with an alive remote side can be attached, which
+        // also prevents the remote side from disposing the data meanwhile.
+        h.attached
+            .compare_exchange(
+                remote,
+                SIDE_A | SIDE_B,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            )
+            .map_err(|_| AttachError {})?;
+
+        // Take over the indices owned by the previous owner of this side.
         unsafe {
-            self.queue_a().drop_in_place();
-            self.queue_b().drop_in_place();
+            if side == SIDE_A {
+                self.queues_a().for_each(|q| q.reset_producer());
+                self.queues_b().for_each(|mut q| q.discard());
+            } else {
+                self.queues_b().for_each(|q| q.reset_producer());
+                self.queues_a().for_each(|mut q| q.discard());
+            }
+        }
+        h.epoch.fetch_add(1, Ordering::Release);
+        h.closed.store(0, Ordering::Relaxed);
+        // The remote side may leave before we reopen the connection.
+        if h.attached.load(Ordering::Relaxed) & remote == 0 {
+            h.closed.store(1, Ordering::Relaxed);
         }
+        h.notify_all();
         Ok(())
     }
 
-    unsafe fn drop_in_place(&mut self) {
+    unsafe fn drop_in_place(&mut self, side: u32) {
         unsafe {
-            if self.dispose().is_ok() {
+            // Memory provided by the user is never released here.
+            #[cfg(feature = "alloc")]
+            if self.dispose(side).is_ok() && self.header().allocated != 0 {
                 let h = self.header.as_ref();
-                dealloc_buffer(self.buf_a, h.off_a.ring_mask as usize + 1);
-                dealloc_buffer(self.buf_b, h.off_b.ring_mask as usize + 1);
+                dealloc_buffer(self.buf_a, h.size_a());
+                dealloc_buffer(self.buf_b, h.size_b());
                 dealloc(self.header);
             }
+            #[cfg(not(feature = "alloc"))]
+            {
+                _ = self.dispose(side);
+            }
         }
     }
 }
 
 pub struct Queue<'a, T> {
     off: &'a Offsets,
+    ev: &'a Events,
     buf: NonNull<T>,
 }
 
 impl<'a, T> Queue<'a, T> {
     pub fn len(&self) -> usize {
-        let head = self.off.head.load(Ordering::Relaxed);
-        let tail = self.off.tail.load(Ordering::Relaxed);
+        let head = self.off.cons.head.load(Ordering::Relaxed);
+        let tail = self.off.prod.tail.load(Ordering::Relaxed);
         (tail.wrapping_sub(head) & self.off.ring_mask) as usize
     }
 
@@ -323,87 +1243,268 @@ impl<'a, T> Queue<'a, T> {
     }
 
     unsafe fn enqueue(&mut self, val: T) -> Result<(), T> {
-        let Self { off, buf } = self;
+        let Self { off, ev, buf } = self;
         debug_assert!((off.ring_mask + 1).is_power_of_two());
 
-        let tail = off.tail.load(Ordering::Relaxed);
-        let head = off.head.load(Ordering::Acquire);
-
-        let next_tail = off.inc(tail);
-        if next_tail == head {
+        let tail = off.prod.tail.load(Ordering::Relaxed);
+        if off.free(tail, 1) == 0 {
+            off.record_full();
             return Err(val);
         }
 
         unsafe { buf.add(tail as usize).write(val) };
-        off.tail.store(next_tail, Ordering::Release);
+        let tail = off.inc(tail);
+        off.prod.tail.store(tail, Ordering::Release);
+        ev.readable.notify();
+        off.record_enqueued(1, tail);
 
         Ok(())
     }
 
     unsafe fn enqueue_bulk(&mut self, mut vals: impl Iterator<Item = T>) -> usize {
-        let Self { off, buf } = self;
+        let Self { off, ev, buf } = self;
         debug_assert!((off.ring_mask + 1).is_power_of_two());
 
-        let mut tail = off.tail.load(Ordering::Relaxed);
-        let head = off.head.load(Ordering::Acquire);
+        let mut tail = off.prod.tail.load(Ordering::Relaxed);
+        let mut free = off.free(tail, u32::MAX);
+        if free == 0 {
+            off.record_full();
+        }
 
         let mut n = 0;
-        let mut next_tail;
-        loop {
-            next_tail = off.inc(tail);
-            if next_tail == head {
-                break;
-            }
+        while free != 0 {
             let Some(val) = vals.next() else {
                 break;
             };
             unsafe { buf.add(tail as usize).write(val) };
-            off.tail.store(next_tail, Ordering::Release);
             n += 1;
-            tail = next_tail;
+            free -= 1;
+            tail = off.inc(tail);
+        }
+        // Publish all written entries at once.
+        if n != 0 {
+            off.prod.tail.store(tail, Ordering::Release);
+            ev.readable.notify();
+            off.record_enqueued(n as u32, tail);
         }
 
         n
     }
 
-    unsafe fn dequeue(&mut self) -> Option<T> {
-        let Self { off, buf } = self;
+    unsafe fn reserve(self, n: usize, locked: bool) -> Reserve<'a, T> {
+        let off = self.off;
         debug_assert!((off.ring_mask + 1).is_power_of_two());
 
-        let head = off.head.load(Ordering::Relaxed);
-        let tail = off.tail.load(Ordering::Acquire);
+        let n = n.try_into().unwrap_or(u32::MAX);
+        let tail = off.prod.tail.load(Ordering::Relaxed);
+        let free = off.free(tail, n);
+        if free == 0 && n != 0 {
+            off.record_full();
+        }
 
-        if head == tail {
+        Reserve {
+            queue: self,
+            tail,
+            cap: free.min(n),
+            len: 0,
+            locked,
+        }
+    }
+
+    /// Resets the claimed index so that concurrent producers can start from
+    /// the current tail.
+    ///
+    /// # Safety
+    ///
+    /// No producer may be active on this queue.
+    unsafe fn init_mp(&self) {
+        let tail = self.off.prod.tail.load(Ordering::Relaxed);
+        self.off.prod.claim.store(tail, Ordering::Relaxed);
+    }
+
+    /// Enqueues a value, which is safe to be called from multiple producers
+    /// simultaneously.
+    ///
+    /// Producers claim slots in turn and publish them in the same order. It
+    /// fails if the queue is full or locked by an exclusive producer.
+    #[cfg(feature = "alloc")]
+    unsafe fn enqueue_mp(&self, val: T) -> Result<(), T> {
+        let Self { off, ev, buf } = self;
+        debug_assert!((off.ring_mask + 1).is_power_of_two());
+
+        // Claims are acquired and released, so that the head loaded below is
+        // at least as new as the one seen by the producer of the claim.
+        let mut claim = off.prod.claim.load(Ordering::Acquire);
+        loop {
+            if claim & CLAIM_LOCKED != 0 {
+                return Err(val);
+            }
+            // Other producers may move past the cached head by a whole lap
+            // while we are preempted, so the head is always loaded here. It
+            // never moves past the claimed index if the claim still holds.
+            let head = off.cons.head.load(Ordering::Acquire);
+            if off.free_from(claim & off.ring_mask, head) == 0 {
+                off.record_full();
+                return Err(val);
+            }
+            let next_claim = claim.wrapping_add(1) & !CLAIM_LOCKED;
+            match off.prod.claim.compare_exchange_weak(
+                claim,
+                next_claim,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => break,
+                Err(c) => claim = c,
+            }
+        }
+
+        let tail = claim & off.ring_mask;
+        unsafe { buf.add(tail as usize).write(val) };
+        // Wait for previous producers to publish their slots. `Acquire`
+        // ensures their writes become visible along with ours.
+        while off.prod.tail.load(Ordering::Acquire) != tail {
+            sync::spin_loop();
+        }
+        let tail = off.inc(tail);
+        off.prod.tail.store(tail, Ordering::Release);
+        ev.readable.notify();
+        off.record_enqueued(1, tail);
+
+        Ok(())
+    }
+
+    /// Locks this queue for an exclusive producer, after which the tail can be
+    /// updated as if it is a single-producer queue.
+    ///
+    /// It returns `false` if another exclusive producer is active, and waits
+    /// until all claimed slots are published otherwise.
+    #[cfg(feature = "alloc")]
+    unsafe fn lock_mp(&self) -> bool {
+        let off = self.off;
+        let mut claim = off.prod.claim.load(Ordering::Relaxed);
+        loop {
+            if claim & CLAIM_LOCKED != 0 {
+                return false;
+            }
+            if off.prod.tail.load(Ordering::Acquire) != claim & off.ring_mask {
+                sync::spin_loop();
+                claim = off.prod.claim.load(Ordering::Relaxed);
+                continue;
+            }
+            match off.prod.claim.compare_exchange_weak(
+                claim,
+                claim | CLAIM_LOCKED,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => {
+                    // The cached head is not maintained by other producers.
+                    off.refresh_head();
+                    return true;
+                },
+                Err(c) => claim = c,
+            }
+        }
+    }
+
+    /// Unlocks this queue from an exclusive producer.
+    ///
+    /// # Safety
+    ///
+    /// This queue must be locked by [`lock_mp`](Self::lock_mp).
+    unsafe fn unlock_mp(&self) {
+        let off = self.off;
+        let claim = off.prod.claim.load(Ordering::Relaxed) & !CLAIM_LOCKED;
+        // The claim keeps running freely, so that a producer preempted before
+        // its `compare_exchange` never matches it again a lap later. An
+        // exclusive producer publishes less than a lap at once, which is not
+        // lost by masking.
+        let tail = off.prod.tail.load(Ordering::Relaxed);
+        let written = tail.wrapping_sub(claim) & off.ring_mask;
+        let claim = claim.wrapping_add(written) & !CLAIM_LOCKED;
+        off.prod.claim.store(claim, Ordering::Release);
+    }
+
+    unsafe fn dequeue(&mut self) -> Option<T> {
+        let Self { off, ev, buf } = self;
+        debug_assert!((off.ring_mask + 1).is_power_of_two());
+
+        let head = off.cons.head.load(Ordering::Relaxed);
+        if head == off.tail(head) {
             return None;
         }
-        let next_head = off.inc(head);
 
         let val = unsafe { buf.add(head as usize).read() };
-        off.head.store(next_head, Ordering::Release);
+        off.cons.head.store(off.inc(head), Ordering::Release);
+        ev.writable.notify();
+        off.record_dequeued(1);
 
         Some(val)
     }
 
+    unsafe fn peek(&self) -> Option<&'a T> {
+        let Self { off, buf, .. } = self;
+        let head = off.cons.head.load(Ordering::Relaxed);
+        if head == off.tail(head) {
+            return None;
+        }
+        Some(unsafe { buf.add(head as usize).as_ref() })
+    }
+
+    unsafe fn recv_guard(self) -> Option<RecvGuard<'a, T>> {
+        let off = self.off;
+        let head = off.cons.head.load(Ordering::Relaxed);
+        if head == off.tail(head) {
+            return None;
+        }
+        Some(RecvGuard { queue: self, head })
+    }
+
     unsafe fn dequeue_bulk(&mut self) -> Drain<'a, T> {
-        let Self { off, buf } = self;
+        let Self { off, ev, buf } = self;
         debug_assert!((off.ring_mask + 1).is_power_of_two());
 
-        let head = off.head.load(Ordering::Relaxed);
-        let tail = off.tail.load(Ordering::Acquire);
+        let head = off.cons.head.load(Ordering::Relaxed);
+        let tail = off.prod.tail.load(Ordering::Acquire);
+        off.cons.cached_tail.store(tail, Ordering::Relaxed);
 
         Drain {
             off,
+            ev,
             buf: *buf,
             head,
             tail,
         }
     }
 
+    /// Drops entries that are currently enqueued, which must be called by the
+    /// consumer.
+    unsafe fn discard(&mut self) {
+        let tail = self.off.prod.tail.load(Ordering::Acquire);
+        let mut head = self.off.cons.head.load(Ordering::Relaxed);
+        while head != tail {
+            unsafe { self.buf.add(head as usize).drop_in_place() };
+            head = self.off.inc(head);
+        }
+        self.off.cons.cached_tail.store(tail, Ordering::Relaxed);
+        self.off.cons.head.store(head, Ordering::Release);
+        self.ev.writable.notify();
+    }
+
+    /// Resets states of the producer after the previous one is gone, which
+    /// drops slots claimed but not published.
+    unsafe fn reset_producer(&self) {
+        let head = self.off.cons.head.load(Ordering::Acquire);
+        self.off.prod.cached_head.store(head, Ordering::Relaxed);
+        unsafe { self.init_mp() };
+    }
+
     unsafe fn drop_in_place(&mut self) {
         debug_assert!((self.off.ring_mask + 1).is_power_of_two());
         unsafe {
-            let mut head = self.off.head.as_ptr().read();
-            let tail = self.off.tail.as_ptr().read();
+            let mut head = self.off.cons.head.load(Ordering::Relaxed);
+            let tail = self.off.prod.tail.load(Ordering::Relaxed);
             while head != tail {
                 self.buf.add(head as usize).drop_in_place();
                 head = self.off.inc(head);
@@ -412,8 +1513,164 @@ impl<'a, T> Queue<'a, T> {
     }
 }
 
+/// A batch of reserved slots in a [`Queue`], created by [`Uring::reserve`].
+pub struct Reserve<'a, T> {
+    queue: Queue<'a, T>,
+    tail: u32,
+    cap: u32,
+    len: u32,
+    /// Whether the queue is locked by this exclusive producer.
+    locked: bool,
+}
+
+impl<T> Reserve<'_, T> {
+    /// Returns the number of reserved slots.
+    pub fn capacity(&self) -> usize {
+        self.cap as usize
+    }
+
+    /// Returns the number of written entries.
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Writes an entry to the next reserved slot, or returns it as an [`Err`]
+    /// if all slots are written.
+    pub fn push(&mut self, val: T) -> Result<(), T> {
+        if self.len == self.cap {
+            return Err(val);
+        }
+        unsafe {
+            self.slot(self.len).write(val);
+        }
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Returns the remaining reserved slots, which are split into two slices
+    /// if they wrap around the end of the buffer.
+    pub fn spare_capacity_mut(&mut self) -> (&mut [MaybeUninit<T>], &mut [MaybeUninit<T>]) {
+        let size = self.queue.off.ring_mask + 1;
+        let start = (self.tail.wrapping_add(self.len)) & self.queue.off.ring_mask;
+        let remaining = self.cap - self.len;
+        let first = remaining.min(size - start);
+        unsafe {
+            let buf = self.queue.buf.cast::<MaybeUninit<T>>();
+            (
+                core::slice::from_raw_parts_mut(buf.add(start as usize).as_ptr(), first as usize),
+                core::slice::from_raw_parts_mut(buf.as_ptr(), (remaining - first) as usize),
+            )
+        }
+    }
+
+    /// Forces the number of written entries to `len`.
+    ///
+    /// # Safety
+    ///
+    /// `len` must not be greater than [`capacity`](Self::capacity), and the
+    /// slots in between must be initialized, e.g. through
+    /// [`spare_capacity_mut`](Self::spare_capacity_mut).
+    pub unsafe fn set_len(&mut self, len: usize) {
+        debug_assert!(len <= self.capacity());
+        self.len = len as u32;
+    }
+
+    /// Publishes all written entries and returns the number of them.
+    pub fn commit(mut self) -> usize {
+        let n = self.len;
+        if n != 0 {
+            let off = self.queue.off;
+            let tail = self.tail.wrapping_add(n) & off.ring_mask;
+            off.prod.tail.store(tail, Ordering::Release);
+            self.queue.ev.readable.notify();
+            off.record_enqueued(n, tail);
+            self.len = 0;
+        }
+        n as usize
+    }
+
+    unsafe fn slot(&self, i: u32) -> NonNull<T> {
+        let Queue { off, buf, .. } = self.queue;
+        unsafe { buf.add((self.tail.wrapping_add(i) & off.ring_mask) as usize) }
+    }
+}
+
+impl<T> Drop for Reserve<'_, T> {
+    fn drop(&mut self) {
+        unsafe {
+            for i in 0..self.len {
+                self.slot(i).drop_in_place();
+            }
+            if self.locked {
+                self.queue.unlock_mp();
+            }
+        }
+    }
+}
+
+/// An entry at the front of a [`Queue`], created by [`Uring::recv_guard`].
+///
+/// The entry is consumed and dropped when this guard is dropped, unless it is
+/// taken by [`accept`](Self::accept) or left in the queue by
+/// [`reject`](Self::reject).
+pub struct RecvGuard<'a, T> {
+    queue: Queue<'a, T>,
+    head: u32,
+}
+
+impl<T> RecvGuard<'_, T> {
+    /// Consumes the entry and returns it.
+    pub fn accept(self) -> T {
+        let this = core::mem::ManuallyDrop::new(self);
+        let val = unsafe { this.slot().read() };
+        this.advance();
+        val
+    }
+
+    /// Leaves the entry in the queue, so that it will be received again.
+    pub fn reject(self) {
+        core::mem::forget(self);
+    }
+
+    fn slot(&self) -> NonNull<T> {
+        unsafe { self.queue.buf.add(self.head as usize) }
+    }
+
+    fn advance(&self) {
+        let Queue { off, ev, .. } = self.queue;
+        off.cons.head.store(off.inc(self.head), Ordering::Release);
+        ev.writable.notify();
+        off.record_dequeued(1);
+    }
+}
+
+impl<T> core::ops::Deref for RecvGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { self.slot().as_ref() }
+    }
+}
+
+impl<T> core::ops::DerefMut for RecvGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { self.slot().as_mut() }
+    }
+}
+
+impl<T> Drop for RecvGuard<'_, T> {
+    fn drop(&mut self) {
+        unsafe { self.slot().drop_in_place() };
+        self.advance();
+    }
+}
+
 pub struct Drain<'a, T> {
     off: &'a Offsets,
+    ev: &'a Events,
     buf: NonNull<T>,
     head: u32,
     tail: u32,
@@ -427,15 +1684,37 @@ impl<T> Iterator for Drain<'_, T> {
         }
         let next_head = self.off.inc(self.head);
         let val = unsafe { self.buf.add(self.head as usize).read() };
-        self.off.head.store(next_head, Ordering::Release);
+        self.off.cons.head.store(next_head, Ordering::Release);
+        self.off.record_dequeued(1);
         self.head = next_head;
         Some(val)
     }
 }
 
+impl<T> Drop for Drain<'_, T> {
+    fn drop(&mut self) {
+        self.ev.writable.notify();
+    }
+}
+
+/// Memory layout of a [`Uring`] built in a single region, returned by
+/// [`Builder::layout`].
+///
+/// All offsets are in bytes, relative to the start of the region.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UringLayout {
+    /// Layout of the entire region.
+    pub layout: Layout,
+    pub header: usize,
+    pub buf_a: usize,
+    pub buf_b: usize,
+}
+
 pub struct Builder<A, B, Ext = ()> {
-    size_a: usize,
-    size_b: usize,
+    size_a: [usize; MAX_LANES],
+    size_b: [usize; MAX_LANES],
+    lanes_a: usize,
+    lanes_b: usize,
     ext: Ext,
     marker: PhantomData<(A, B)>,
 }
@@ -450,45 +1729,152 @@ impl<A, B, Ext> Builder<A, B, Ext> {
 
     pub fn new_ext(ext: Ext) -> Self {
         Self {
-            size_a: 32,
-            size_b: 32,
+            size_a: [32; MAX_LANES],
+            size_b: [32; MAX_LANES],
+            lanes_a: 1,
+            lanes_b: 1,
             ext,
             marker: PhantomData,
         }
     }
 
+    /// Sets the size of lane `0` of the A side.
     pub fn size_a(&mut self, size: usize) -> &mut Self {
         assert!(size.is_power_of_two());
-        self.size_a = size;
+        self.size_a[0] = size;
         self
     }
 
+    /// Sets the size of lane `0` of the B side.
     pub fn size_b(&mut self, size: usize) -> &mut Self {
         assert!(size.is_power_of_two());
-        self.size_b = size;
+        self.size_b[0] = size;
         self
     }
 
+    /// Adds a lane of the given size to the A side, whose priority is lower
+    /// than all existing lanes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are already [`MAX_LANES`] lanes.
+    pub fn lane_a(&mut self, size: usize) -> &mut Self {
+        assert!(size.is_power_of_two());
+        assert!(self.lanes_a < MAX_LANES, "too many lanes");
+        self.size_a[self.lanes_a] = size;
+        self.lanes_a += 1;
+        self
+    }
+
+    /// Adds a lane of the given size to the B side, whose priority is lower
+    /// than all existing lanes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are already [`MAX_LANES`] lanes.
+    pub fn lane_b(&mut self, size: usize) -> &mut Self {
+        assert!(size.is_power_of_two());
+        assert!(self.lanes_b < MAX_LANES, "too many lanes");
+        self.size_b[self.lanes_b] = size;
+        self.lanes_b += 1;
+        self
+    }
+
+    /// Returns the memory layout to build a [`Uring`] in a single region, see
+    /// [`build_in`](Self::build_in).
+    pub fn layout(&self) -> UringLayout {
+        let size_a = self.size_a[..self.lanes_a].iter().sum();
+        let size_b = self.size_b[..self.lanes_b].iter().sum();
+        let header = Layout::new::<Header<Ext>>();
+        let (layout, buf_a) = header.extend(Layout::array::<A>(size_a).unwrap()).unwrap();
+        let (layout, buf_b) = layout.extend(Layout::array::<B>(size_b).unwrap()).unwrap();
+        UringLayout {
+            layout: layout.pad_to_align(),
+            header: 0,
+            buf_a,
+            buf_b,
+        }
+    }
+
+    /// Builds a [`Uring`] in the given memory region.
+    ///
+    /// The returned [`RawUring`] can be converted to both sides through
+    /// `from_raw`. Since the memory is not allocated by the global allocator,
+    /// it is never deallocated when both sides are dropped, and should be
+    /// released by the caller after `dispose_raw`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be valid for writes of the [`layout`](Self::layout) of this
+    /// [`Builder`], and must be properly aligned.
+    pub unsafe fn build_in(self, ptr: NonNull<u8>) -> RawUring<A, B, Ext> {
+        let l = self.layout();
+        debug_assert!(ptr.addr().get() % l.layout.align() == 0);
+        unsafe {
+            let header = ptr.byte_add(l.header).cast::<Header<Ext>>();
+            header.write(self.build_header());
+            RawUring {
+                header,
+                buf_a: ptr.byte_add(l.buf_a).cast(),
+                buf_b: ptr.byte_add(l.buf_b).cast(),
+                marker: PhantomData,
+            }
+        }
+    }
+
     pub fn build_header(self) -> Header<Ext> {
+        fn offsets(sizes: &[usize], lanes: usize) -> [Offsets; MAX_LANES] {
+            let mut start = 0u32;
+            core::array::from_fn(|i| {
+                if i >= lanes {
+                    return Offsets::new(1, 0);
+                }
+                let size = u32::try_from(sizes[i]).expect("lane too large");
+                let off = Offsets::new(size, start);
+                start = start.checked_add(size).expect("lanes too large");
+                off
+            })
+        }
+
         Header {
-            off_a: Offsets::new(self.size_a as u32),
-            off_b: Offsets::new(self.size_b as u32),
-            rc: AtomicU32::new(2),
+            layout: HeaderLayout::new::<A, B, Ext>(),
+            off_a: offsets(&self.size_a, self.lanes_a),
+            off_b: offsets(&self.size_b, self.lanes_b),
+            ev_a: Events::new(),
+            ev_b: Events::new(),
+            lanes_a: self.lanes_a as u32,
+            lanes_b: self.lanes_b as u32,
+            attached: AtomicU32::new(SIDE_A | SIDE_B),
+            closed: AtomicU32::new(0),
+            epoch: AtomicU32::new(0),
+            allocated: 0,
             ext: self.ext,
         }
     }
 
+    /// Builds a connection whose A side accepts multiple producers.
+    ///
+    /// See [`UringMpsc`] for more information.
+    #[cfg(feature = "alloc")]
+    pub fn build_mpsc(self) -> (UringMpsc<UringA<A, B, Ext>>, UringB<A, B, Ext>) {
+        let (pa, pb) = self.build();
+        (UringMpsc::new(pa), pb)
+    }
+
+    #[cfg(feature = "alloc")]
     pub fn build(self) -> (UringA<A, B, Ext>, UringB<A, B, Ext>) {
         let header;
         let buf_a;
         let buf_b;
 
         unsafe {
+            let mut h = self.build_header();
+            h.allocated = 1;
             header = alloc::<Header<Ext>>();
-            buf_a = alloc_buffer(self.size_a);
-            buf_b = alloc_buffer(self.size_b);
+            buf_a = alloc_buffer(h.size_a());
+            buf_b = alloc_buffer(h.size_b());
 
-            header.write(self.build_header());
+            header.write(h);
         }
 
         let ring_a = UringA(RawUring {
@@ -514,6 +1900,7 @@ impl<A, B, Ext: Default> Default for Builder<A, B, Ext> {
     }
 }
 
+#[cfg(feature = "alloc")]
 unsafe fn alloc_buffer<T>(size: usize) -> NonNull<T> {
     let layout = Layout::array::<T>(size).unwrap();
     NonNull::new(unsafe { alloc::alloc::alloc(layout) })
@@ -521,6 +1908,7 @@ unsafe fn alloc_buffer<T>(size: usize) -> NonNull<T> {
         .cast()
 }
 
+#[cfg(feature = "alloc")]
 unsafe fn alloc<T>() -> NonNull<T> {
     let layout = Layout::new::<T>();
     NonNull::new(unsafe { alloc::alloc::alloc(layout) })
@@ -528,17 +1916,19 @@ unsafe fn alloc<T>() -> NonNull<T> {
         .cast()
 }
 
+#[cfg(feature = "alloc")]
 unsafe fn dealloc_buffer<T>(ptr: NonNull<T>, size: usize) {
     let layout = Layout::array::<T>(size).unwrap();
     unsafe { alloc::alloc::dealloc(ptr.as_ptr().cast(), layout) }
 }
 
+#[cfg(feature = "alloc")]
 unsafe fn dealloc<T>(ptr: NonNull<T>) {
     let layout = Layout::new::<T>();
     unsafe { alloc::alloc::dealloc(ptr.as_ptr().cast(), layout) }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "alloc", not(loom)))]
 mod tests {
     use std::sync::atomic::{AtomicBool, AtomicUsize};
 
@@ -564,6 +1954,295 @@ mod tests {
         }
     }
 
+    #[test]
+    fn uring_reserve() {
+        static DROP_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+        struct DropCounter(usize);
+        impl Drop for DropCounter {
+            fn drop(&mut self) {
+                DROP_COUNT.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let (mut pa, mut pb) = Builder::<DropCounter, ()>::new().build();
+        // Move the tail close to the end of the buffer.
+        for i in 0..30 {
+            pa.send(DropCounter(i)).unwrap_or_else(|_| unreachable!());
+            pb.recv().unwrap();
+        }
+
+        let mut batch = pa.reserve(8);
+        assert_eq!(batch.capacity(), 8);
+        batch
+            .push(DropCounter(0))
+            .unwrap_or_else(|_| unreachable!());
+        batch
+            .push(DropCounter(1))
+            .unwrap_or_else(|_| unreachable!());
+        drop(batch);
+        assert!(pb.recv().is_none());
+        assert_eq!(DROP_COUNT.load(Ordering::Relaxed), 32);
+
+        let mut batch = pa.reserve(64);
+        assert_eq!(batch.capacity(), 31);
+        let (first, second) = batch.spare_capacity_mut();
+        assert_eq!((first.len(), second.len()), (2, 29));
+        for (i, slot) in first.iter_mut().chain(second.iter_mut()).enumerate() {
+            slot.write(DropCounter(i));
+        }
+        unsafe { batch.set_len(31) };
+        assert!(batch.push(DropCounter(31)).is_err());
+        assert_eq!(batch.commit(), 31);
+        assert_eq!(pa.sender().len(), 31);
+        for i in 0..31 {
+            assert_eq!(pb.recv().unwrap().0, i);
+        }
+        assert!(pb.recv().is_none());
+    }
+
+    #[test]
+    fn uring_chain() {
+        #[derive(Debug, PartialEq)]
+        struct Entry(i32, bool);
+        impl Linked for Entry {
+            fn set_linked(&mut self, linked: bool) {
+                self.1 = linked;
+            }
+        }
+
+        let mut b = Builder::<Entry, ()>::new();
+        b.size_a(4);
+        let (mut pa, mut pb) = b.build();
+        let chain = |n| (0..n).map(|i| Entry(i, false));
+        // A chain is never sent partially.
+        assert_eq!(pa.send_chain(chain(4)).unwrap_err().len(), 4);
+        assert!(pb.recv().is_none());
+        pa.send_chain(chain(3)).unwrap();
+        assert_eq!(pb.recv_bulk().collect::<Vec<_>>(), [
+            Entry(0, true),
+            Entry(1, true),
+            Entry(2, false)
+        ]);
+    }
+
+    #[test]
+    fn uring_lanes() {
+        let mut b = Builder::<i32, i32>::new();
+        b.size_a(4).lane_a(8).lane_a(2);
+        let (mut pa, mut pb) = b.build();
+        assert_eq!(pa.header().lanes_a(), 3);
+        assert_eq!(pa.header().lanes_b(), 1);
+        assert_eq!(pa.header().size_a(), 14);
+        assert_eq!(pa.header().lane_size_a(1), Some(8));
+        assert!(pa.sender_lane(3).is_none());
+        assert!(pb.sender_lane(1).is_none());
+
+        assert_eq!(pa.send_bulk_to(1, 10..20), 7);
+        assert_eq!(pa.send_to(2, 20), Ok(()));
+        assert_eq!(pa.send_to(2, 21), Err(21));
+        assert_eq!(pa.send_bulk(0..2), 2);
+
+        // Entries are received by the priority of lanes.
+        assert_eq!(pb.recv(), Some(0));
+        assert_eq!(pb.recv_from(2), Some(20));
+        assert_eq!(pb.recv_bulk().collect::<Vec<_>>(), [1]);
+        assert_eq!(
+            pb.recv_bulk().collect::<Vec<_>>(),
+            (10..17).collect::<Vec<_>>()
+        );
+        assert_eq!(pb.recv(), None);
+
+        drop((pa, pb));
+
+        // Entries in all lanes are dropped along with the uring.
+        let rc = std::rc::Rc::new(());
+        let mut b = Builder::<_, ()>::new();
+        b.lane_a(2);
+        let (mut pa, pb) = b.build();
+        pa.send(rc.clone()).unwrap();
+        pa.send_to(1, rc.clone()).unwrap();
+        drop((pa, pb));
+        assert_eq!(std::rc::Rc::strong_count(&rc), 1);
+    }
+
+    #[test]
+    fn uring_recv_guard() {
+        let (mut pa, mut pb) = Builder::<String, ()>::new().build();
+        assert!(pb.peek().is_none());
+        assert!(pb.recv_guard().is_none());
+        pa.send("a".to_owned()).unwrap();
+        pa.send("b".to_owned()).unwrap();
+        pa.send("c".to_owned()).unwrap();
+
+        assert_eq!(pb.peek().map(String::as_str), Some("a"));
+        let mut guard = pb.recv_guard().unwrap();
+        guard.push('!');
+        guard.reject();
+        assert_eq!(pb.recv_guard().unwrap().accept(), "a!");
+        // Dropping a guard consumes the entry.
+        assert_eq!(*pb.recv_guard().unwrap(), "b");
+        assert_eq!(pb.peek().map(String::as_str), Some("c"));
+        assert_eq!(pb.recv().as_deref(), Some("c"));
+        assert!(pb.recv_guard().is_none());
+    }
+
+    #[test]
+    fn uring_build_in() {
+        let mut b = Builder::<u8, u64>::new();
+        b.size_a(4).lane_a(2).size_b(8);
+        let l = b.layout();
+        assert_eq!(l.header, 0);
+        assert!(l.buf_a >= size_of::<Header>());
+        assert_eq!(l.buf_b % align_of::<u64>(), 0);
+        assert!(l.buf_b >= l.buf_a + 6);
+        assert!(l.layout.size() >= l.buf_b + size_of::<[u64; 8]>());
+
+        unsafe {
+            let ptr = NonNull::new(std::alloc::alloc(l.layout)).unwrap();
+            let raw = b.build_in(ptr);
+            assert_eq!(raw.header.cast(), ptr);
+            let mut pa = UringA::from_raw(RawUring {
+                header: raw.header,
+                buf_a: raw.buf_a,
+                buf_b: raw.buf_b,
+                marker: PhantomData,
+            });
+            let mut pb = UringB::from_raw(raw);
+            assert_eq!(pa.header().size_a(), 6);
+            pa.send_to(1, 1).unwrap();
+            pb.send(2).unwrap();
+            assert_eq!(pb.recv(), Some(1));
+            assert_eq!(pa.recv(), Some(2));
+
+            assert!(pa.dispose_raw().is_err());
+            pb.dispose_raw().unwrap();
+
+            // Dropping both sides never deallocates the memory.
+            let mut b = Builder::<u8, u64>::new();
+            b.size_a(4).lane_a(2).size_b(8);
+            let raw = b.build_in(ptr);
+            let pa = UringA::from_raw(RawUring {
+                header: raw.header,
+                buf_a: raw.buf_a,
+                buf_b: raw.buf_b,
+                marker: PhantomData,
+            });
+            drop((pa, UringB::from_raw(raw)));
+            std::alloc::dealloc(ptr.as_ptr(), l.layout);
+        }
+    }
+
+    fn blocking_with<W: WaitStrategy + Sync>(wait: &W) {
+        let mut b = Builder::<usize, usize>::new();
+        b.size_a(4).size_b(4);
+        let (mut pa, mut pb) = b.build();
+        std::thread::scope(|cx| {
+            cx.spawn(move || {
+                for i in 0..64 {
+                    pa.send_blocking(i, wait).unwrap();
+                    assert_eq!(pa.recv_blocking(wait), Some(i));
+                }
+                drop(pa);
+            });
+            cx.spawn(|| {
+                let mut n = 0;
+                while let Some(i) = pb.recv_blocking(wait) {
+                    pb.send_blocking(i, wait).unwrap();
+                    n += 1;
+                }
+                // Returns `None` after the remote side is dropped.
+                assert_eq!(n, 64);
+            });
+        });
+    }
+
+    #[test]
+    fn uring_blocking() {
+        blocking_with(&Spin);
+        #[cfg(all(feature = "futex", target_os = "linux"))]
+        blocking_with(&Futex);
+    }
+
+    #[test]
+    #[cfg(all(feature = "futex", target_os = "linux"))]
+    fn uring_polling() {
+        use std::time::Duration;
+
+        let (mut pa, mut pb) = Builder::<usize, ()>::new().build();
+        std::thread::scope(|cx| {
+            cx.spawn(move || {
+                let mut n = 0;
+                let (idle, timeout) = (Duration::from_millis(1), Duration::from_secs(10));
+                while let Some(i) = pb.recv_polling(&Futex, idle, timeout) {
+                    assert_eq!(i, n);
+                    n += 1;
+                }
+                assert_eq!(n, 3);
+            });
+            for i in 0..3 {
+                // Wait for the receiver to fall asleep, then wake it up.
+                while !pa.peer_needs_wakeup() {
+                    std::thread::yield_now();
+                }
+                pa.send(i).unwrap();
+            }
+            drop(pa);
+        });
+    }
+
+    #[test]
+    fn header_layout() {
+        let mut header = Builder::<u32, u64>::new().build_header();
+        assert_eq!(header.check_layout::<u32, u64>(), Ok(()));
+        assert!(matches!(
+            header.check_layout::<u32, u32>(),
+            Err(LayoutError::Mismatch { name: "B", .. })
+        ));
+
+        header.layout.version += 1;
+        assert!(matches!(
+            header.check_layout::<u32, u64>(),
+            Err(LayoutError::Version(_))
+        ));
+        header.layout.magic = 0;
+        assert_eq!(
+            header.check_layout::<u32, u64>(),
+            Err(LayoutError::Magic(0))
+        );
+    }
+
+    #[test]
+    fn uring_attach() {
+        let (mut pa, mut pb) = Builder::<i32, i32>::new().build();
+        let raw = |pa: &RawUring<i32, i32>| {
+            let mut raw = RawUring::dangling();
+            raw.header = pa.header;
+            raw.buf_a = pa.buf_a;
+            raw.buf_b = pa.buf_b;
+            raw
+        };
+        pa.send(1).unwrap();
+        pb.send(2).unwrap();
+        let session = pb.session();
+
+        // Side A dies without dropping.
+        let raw_a = pa.into_raw();
+        assert!(unsafe { UringA::attach(raw(&raw_a)) }.is_err());
+        unsafe { pb.detach_peer() };
+        assert!(!pb.is_connected());
+
+        let mut pa = unsafe { UringA::attach(raw_a) }.unwrap();
+        assert!(pb.is_connected());
+        assert_eq!(pb.session(), session + 1);
+        // Responses to the previous session are discarded.
+        assert_eq!(pa.recv(), None);
+        // Requests from the previous session are kept.
+        assert_eq!(pb.recv(), Some(1));
+        pa.send(3).unwrap();
+        assert_eq!(pb.recv(), Some(3));
+    }
+
     #[test]
     fn uring_drop() {
         static DROP_COUNT: AtomicUsize = AtomicUsize::new(0);
@@ -686,4 +2365,97 @@ mod tests {
             });
         });
     }
+
+    #[derive(Clone, Debug)]
+    enum QueueOp {
+        Send(u8),
+        SendBulk(Vec<u8>),
+        Reserve(usize, Vec<u8>, bool),
+        Recv,
+        RecvBulk(usize),
+        Peek,
+        RecvGuard(bool),
+    }
+
+    fn queue_op() -> impl proptest::strategy::Strategy<Value = QueueOp> {
+        use proptest::prelude::*;
+        let vals = || proptest::collection::vec(any::<u8>(), 0..8);
+        prop_oneof![
+            any::<u8>().prop_map(QueueOp::Send),
+            vals().prop_map(QueueOp::SendBulk),
+            (0..8usize, vals(), any::<bool>()).prop_map(|(n, v, c)| QueueOp::Reserve(n, v, c)),
+            Just(QueueOp::Recv),
+            (0..8usize).prop_map(QueueOp::RecvBulk),
+            Just(QueueOp::Peek),
+            any::<bool>().prop_map(QueueOp::RecvGuard),
+        ]
+    }
+
+    proptest::proptest! {
+        /// Compares a queue against a [`VecDeque`] of the same capacity.
+        #[test]
+        fn queue_model(
+            shift in 0..4u32,
+            ops in proptest::collection::vec(queue_op(), 0..64),
+        ) {
+            use std::collections::VecDeque;
+
+            let cap = (1 << shift) - 1;
+            let mut b = Builder::<u8, ()>::new();
+            b.size_a(1 << shift);
+            let (mut pa, mut pb) = b.build();
+            let mut model = VecDeque::new();
+
+            for op in ops {
+                match op {
+                    QueueOp::Send(v) => {
+                        let expected = if model.len() < cap {
+                            model.push_back(v);
+                            Ok(())
+                        } else {
+                            Err(v)
+                        };
+                        proptest::prop_assert_eq!(pa.send(v), expected);
+                    },
+                    QueueOp::SendBulk(vals) => {
+                        let n = vals.len().min(cap - model.len());
+                        model.extend(&vals[..n]);
+                        proptest::prop_assert_eq!(pa.send_bulk(vals.into_iter()), n);
+                    },
+                    QueueOp::Reserve(n, vals, commit) => {
+                        let mut r = pa.reserve(n);
+                        proptest::prop_assert_eq!(r.capacity(), n.min(cap - model.len()));
+                        let pushed = vals
+                            .into_iter()
+                            .take_while(|&v| r.push(v).is_ok())
+                            .collect::<Vec<_>>();
+                        proptest::prop_assert_eq!(r.len(), pushed.len());
+                        if commit {
+                            proptest::prop_assert_eq!(r.commit(), pushed.len());
+                            model.extend(pushed);
+                        }
+                    },
+                    QueueOp::Recv => proptest::prop_assert_eq!(pb.recv(), model.pop_front()),
+                    QueueOp::RecvBulk(n) => {
+                        let n = n.min(model.len());
+                        let expected = model.drain(..n).collect::<Vec<_>>();
+                        proptest::prop_assert_eq!(pb.recv_bulk().take(n).collect::<Vec<_>>(), expected);
+                    },
+                    QueueOp::Peek => proptest::prop_assert_eq!(pb.peek(), model.front()),
+                    QueueOp::RecvGuard(accept) => {
+                        let guard = pb.recv_guard();
+                        proptest::prop_assert_eq!(guard.as_deref(), model.front());
+                        match guard {
+                            Some(g) if accept => {
+                                proptest::prop_assert_eq!(Some(g.accept()), model.pop_front())
+                            },
+                            Some(g) => g.reject(),
+                            None => {},
+                        }
+                    },
+                }
+                proptest::prop_assert_eq!(pa.sender().len(), model.len());
+            }
+        }
+    }
 }
@@ -0,0 +1,542 @@
+use core::alloc::Layout;
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem::MaybeUninit;
+use core::ptr::NonNull;
+
+use super::sync::{AtomicU32, AtomicU64, Ordering, fence};
+use super::wait::{self, Event, Park};
+use super::{CachePadded, HeaderLayout, LayoutError, TypeLayout, WaitStrategy};
+
+/// An error returned by [`BroadcastReceiver`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BroadcastError {
+    /// There is no new entry, which is never returned by
+    /// [`recv_blocking`](BroadcastReceiver::recv_blocking).
+    Empty,
+    /// The receiver fell behind and the given number of entries were
+    /// overwritten, after which it continues from the oldest entry.
+    Lagged(u64),
+    /// The sender is closed and all entries have been received.
+    Closed,
+}
+
+impl fmt::Display for BroadcastError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => f.write_str("broadcast is empty"),
+            Self::Lagged(n) => write!(f, "broadcast receiver lagged by {n} entries"),
+            Self::Closed => f.write_str("broadcast is closed"),
+        }
+    }
+}
+
+impl core::error::Error for BroadcastError {}
+
+/// The shared header of a broadcast ring.
+///
+/// Like [`Header`](super::Header), it starts with a [`HeaderLayout`], which
+/// is validated by [`check_layout`](Self::check_layout).
+#[repr(C)]
+pub struct BroadcastHeader {
+    layout: HeaderLayout,
+    ring_mask: u32,
+    /// Number of alive receivers.
+    subscribers: AtomicU32,
+    closed: AtomicU32,
+    /// Free-running position of the next entry to write.
+    tail: CachePadded<AtomicU64>,
+    /// Notified when entries are published or the sender is closed.
+    ev: Event,
+}
+
+impl BroadcastHeader {
+    pub const MAGIC: u32 = u32::from_be_bytes(*b"EVBC");
+
+    /// Checks whether this [`BroadcastHeader`] is built for entries of `T` in
+    /// the current format.
+    pub fn check_layout<T>(&self) -> Result<(), LayoutError> {
+        let found = &self.layout;
+        if found.magic != Self::MAGIC {
+            return Err(LayoutError::Magic(found.magic));
+        }
+        if found.version != HeaderLayout::VERSION {
+            return Err(LayoutError::Version(found.version));
+        }
+        let expected = Self::format::<T>();
+        for (name, expected, found) in [
+            ("BroadcastHeader", expected.header, found.header),
+            ("T", expected.a, found.a),
+        ] {
+            if expected != found {
+                return Err(LayoutError::Mismatch {
+                    name,
+                    expected,
+                    found,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the number of slots.
+    pub fn size(&self) -> usize {
+        self.ring_mask as usize + 1
+    }
+
+    /// Returns the number of alive receivers.
+    pub fn subscribers(&self) -> usize {
+        self.subscribers.load(Ordering::Relaxed) as usize
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire) != 0
+    }
+
+    fn format<T>() -> HeaderLayout {
+        HeaderLayout {
+            magic: Self::MAGIC,
+            version: HeaderLayout::VERSION,
+            header: TypeLayout::of::<Self>(),
+            a: TypeLayout::of::<T>(),
+            b: TypeLayout::of::<()>(),
+            ext: TypeLayout::of::<()>(),
+        }
+    }
+}
+
+/// A slot of a broadcast ring.
+///
+/// The sequence is odd while the entry is being written, and equals to
+/// `2 * (position + 1)` once the entry of that position is published.
+#[repr(C)]
+pub struct BroadcastSlot<T> {
+    seq: AtomicU64,
+    val: UnsafeCell<MaybeUninit<T>>,
+}
+
+impl<T> BroadcastSlot<T> {
+    fn stamp(pos: u64) -> u64 {
+        2 * (pos + 1)
+    }
+}
+
+/// Memory layout of a broadcast ring built in a single region, returned by
+/// [`RawBroadcast::layout`].
+///
+/// All offsets are in bytes, relative to the start of the region.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BroadcastLayout {
+    /// Layout of the entire region.
+    pub layout: Layout,
+    pub header: usize,
+    pub buf: usize,
+}
+
+/// Pointers to the memory of a broadcast ring.
+pub struct RawBroadcast<T> {
+    pub header: NonNull<BroadcastHeader>,
+    pub buf: NonNull<BroadcastSlot<T>>,
+}
+
+impl<T> RawBroadcast<T> {
+    pub const fn dangling() -> Self {
+        Self {
+            header: NonNull::dangling(),
+            buf: NonNull::dangling(),
+        }
+    }
+
+    /// Returns the memory layout of a broadcast ring of `size` slots.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is not a power of two, or is less than `2`.
+    pub fn layout(size: usize) -> BroadcastLayout {
+        assert!(size.is_power_of_two() && size >= 2);
+        let header = Layout::new::<BroadcastHeader>();
+        let (layout, buf) = header
+            .extend(Layout::array::<BroadcastSlot<T>>(size).unwrap())
+            .unwrap();
+        BroadcastLayout {
+            layout: layout.pad_to_align(),
+            header: 0,
+            buf,
+        }
+    }
+
+    /// Builds a broadcast ring of `size` slots in the given memory region.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be valid for writes of the [`layout`](Self::layout) of the
+    /// given `size`, and must be properly aligned.
+    pub unsafe fn build_in(size: usize, ptr: NonNull<u8>) -> Self {
+        let l = Self::layout(size);
+        debug_assert!(ptr.addr().get() % l.layout.align() == 0);
+        unsafe {
+            let header = ptr.byte_add(l.header).cast::<BroadcastHeader>();
+            header.write(BroadcastHeader {
+                layout: BroadcastHeader::format::<T>(),
+                ring_mask: size as u32 - 1,
+                subscribers: AtomicU32::new(0),
+                closed: AtomicU32::new(0),
+                tail: CachePadded(AtomicU64::new(0)),
+                ev: Event::new(),
+            });
+            let buf = ptr.byte_add(l.buf).cast::<BroadcastSlot<T>>();
+            for i in 0..size {
+                buf.add(i).write(BroadcastSlot {
+                    seq: AtomicU64::new(0),
+                    val: UnsafeCell::new(MaybeUninit::uninit()),
+                });
+            }
+            Self { header, buf }
+        }
+    }
+
+    unsafe fn header(&self) -> &BroadcastHeader {
+        unsafe { self.header.as_ref() }
+    }
+
+    unsafe fn slot(&self, pos: u64) -> &BroadcastSlot<T> {
+        unsafe {
+            let mask = self.header().ring_mask as u64;
+            self.buf.add((pos & mask) as usize).as_ref()
+        }
+    }
+}
+
+/// A single-producer, multi-consumer broadcast ring.
+///
+/// Every entry is delivered to all receivers, each of which keeps its own
+/// cursor. The sender never waits for receivers. Instead, it overwrites the
+/// oldest entry once the ring is full, and receivers that fall behind find out
+/// through [`BroadcastError::Lagged`].
+///
+/// Entries are copied out by each receiver, hence `T` must be [`Copy`].
+#[cfg(feature = "alloc")]
+pub struct Broadcast<T> {
+    raw: RawBroadcast<T>,
+    ptr: NonNull<u8>,
+    layout: Layout,
+    split: core::sync::atomic::AtomicBool,
+}
+
+#[cfg(feature = "alloc")]
+unsafe impl<T: Send> Sync for Broadcast<T> {}
+#[cfg(feature = "alloc")]
+unsafe impl<T: Send> Send for Broadcast<T> {}
+
+#[cfg(feature = "alloc")]
+impl<T: Copy> Broadcast<T> {
+    /// Allocates a broadcast ring of `size` slots, which keeps the latest
+    /// `size - 1` entries for receivers.
+    pub fn new(size: usize) -> Self {
+        let layout = RawBroadcast::<T>::layout(size).layout;
+        let ptr = NonNull::new(unsafe { alloc::alloc::alloc(layout) })
+            .unwrap_or_else(|| alloc::alloc::handle_alloc_error(layout));
+        Self {
+            raw: unsafe { RawBroadcast::build_in(size, ptr) },
+            ptr,
+            layout,
+            split: core::sync::atomic::AtomicBool::new(false),
+        }
+    }
+
+    /// Returns the sender, or [`None`] if it has been taken before.
+    pub fn sender(&self) -> Option<BroadcastSender<'_, T>> {
+        if self.split.swap(true, core::sync::atomic::Ordering::Relaxed) {
+            return None;
+        }
+        Some(unsafe { BroadcastSender::from_raw(self.raw()) })
+    }
+
+    /// Returns a new receiver, which only sees entries sent after now.
+    pub fn subscribe(&self) -> BroadcastReceiver<'_, T> {
+        unsafe { BroadcastReceiver::from_raw(self.raw()) }
+    }
+
+    fn raw(&self) -> RawBroadcast<T> {
+        RawBroadcast {
+            header: self.raw.header,
+            buf: self.raw.buf,
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T> Drop for Broadcast<T> {
+    fn drop(&mut self) {
+        unsafe { alloc::alloc::dealloc(self.ptr.as_ptr(), self.layout) }
+    }
+}
+
+/// The sending side of a broadcast ring, see [`Broadcast`].
+///
+/// The ring is closed once the sender is dropped.
+pub struct BroadcastSender<'a, T> {
+    raw: RawBroadcast<T>,
+    marker: PhantomData<&'a ()>,
+}
+
+unsafe impl<T: Send> Send for BroadcastSender<'_, T> {}
+
+impl<'a, T: Copy> BroadcastSender<'a, T> {
+    /// # Safety
+    ///
+    /// The specified [`RawBroadcast`] must point to a valid broadcast ring
+    /// which outlives `'a`, and there must be no other sender.
+    pub unsafe fn from_raw(raw: RawBroadcast<T>) -> Self {
+        Self {
+            raw,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn header(&self) -> &BroadcastHeader {
+        unsafe { self.raw.header() }
+    }
+
+    /// Publishes an entry to all receivers, overwriting the oldest entry if
+    /// the ring is full.
+    pub fn send(&mut self, val: T) {
+        let h = self.header();
+        let pos = h.tail.load(Ordering::Relaxed);
+        let slot = unsafe { self.raw.slot(pos) };
+        // Receivers that read the slot meanwhile find the sequence changed.
+        slot.seq
+            .store(BroadcastSlot::<T>::stamp(pos) - 1, Ordering::Relaxed);
+        fence(Ordering::Release);
+        unsafe { slot.val.get().write_volatile(MaybeUninit::new(val)) };
+        slot.seq
+            .store(BroadcastSlot::<T>::stamp(pos), Ordering::Release);
+        h.tail.store(pos + 1, Ordering::Release);
+        h.ev.notify();
+    }
+
+    /// Returns a new receiver, which only sees entries sent after now.
+    pub fn subscribe(&self) -> BroadcastReceiver<'a, T> {
+        unsafe {
+            BroadcastReceiver::from_raw(RawBroadcast {
+                header: self.raw.header,
+                buf: self.raw.buf,
+            })
+        }
+    }
+}
+
+impl<T> BroadcastSender<'_, T> {
+    /// Closes the ring, after which receivers get [`BroadcastError::Closed`] once
+    /// they receive all remaining entries.
+    pub fn close(&self) {
+        let h = unsafe { self.raw.header() };
+        h.closed.store(1, Ordering::Release);
+        h.ev.notify();
+    }
+}
+
+impl<T> Drop for BroadcastSender<'_, T> {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+/// The receiving side of a broadcast ring, see [`Broadcast`].
+///
+/// Each receiver keeps its own cursor, and cloning a receiver also clones the
+/// cursor.
+pub struct BroadcastReceiver<'a, T> {
+    raw: RawBroadcast<T>,
+    next: u64,
+    marker: PhantomData<&'a ()>,
+}
+
+unsafe impl<T: Send> Send for BroadcastReceiver<'_, T> {}
+
+impl<T> BroadcastReceiver<'_, T> {
+    /// Subscribes to a broadcast ring, starting after the latest entry.
+    ///
+    /// # Safety
+    ///
+    /// The specified [`RawBroadcast`] must point to a valid broadcast ring
+    /// which outlives `'a`.
+    pub unsafe fn from_raw(raw: RawBroadcast<T>) -> Self {
+        let h = unsafe { raw.header() };
+        h.subscribers.fetch_add(1, Ordering::Relaxed);
+        let next = h.tail.load(Ordering::Acquire);
+        Self {
+            raw,
+            next,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn header(&self) -> &BroadcastHeader {
+        unsafe { self.raw.header() }
+    }
+
+    /// Returns the number of entries that have not been received, which may
+    /// exceed the size of the ring if this receiver lags behind.
+    pub fn len(&self) -> usize {
+        let tail = self.header().tail.load(Ordering::Acquire);
+        (tail - self.next) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Skips entries that have been overwritten, and returns the number of
+    /// skipped entries.
+    fn lag(&mut self, tail: u64) -> u64 {
+        // The entry at `tail` may be being written, which overwrites the slot
+        // of `tail - size`.
+        let oldest = (tail + 1).saturating_sub(self.header().size() as u64);
+        let skipped = oldest.saturating_sub(self.next);
+        self.next = self.next.max(oldest);
+        skipped
+    }
+}
+
+impl<T: Copy> BroadcastReceiver<'_, T> {
+    pub fn try_recv(&mut self) -> Result<T, BroadcastError> {
+        let h = self.header();
+        // Check `closed` first, so that entries sent before closing are seen.
+        let closed = h.is_closed();
+        let tail = h.tail.load(Ordering::Acquire);
+        if self.next == tail {
+            return Err(if closed {
+                BroadcastError::Closed
+            } else {
+                BroadcastError::Empty
+            });
+        }
+        if self.next + (h.size() as u64) <= tail {
+            return Err(BroadcastError::Lagged(self.lag(tail)));
+        }
+
+        let slot = unsafe { self.raw.slot(self.next) };
+        let seq = slot.seq.load(Ordering::Acquire);
+        if seq == BroadcastSlot::<T>::stamp(self.next) {
+            let val = unsafe { slot.val.get().read_volatile() };
+            fence(Ordering::Acquire);
+            if slot.seq.load(Ordering::Relaxed) == seq {
+                self.next += 1;
+                return Ok(unsafe { val.assume_init() });
+            }
+        }
+        // The slot is overwritten before or while we read it.
+        let tail = self.header().tail.load(Ordering::Acquire);
+        Err(BroadcastError::Lagged(self.lag(tail)))
+    }
+
+    /// Receives an entry, waiting for new entries with the given strategy if
+    /// there is none.
+    pub fn recv_blocking<W: WaitStrategy>(&mut self, wait: &W) -> Result<T, BroadcastError> {
+        wait::park_for(
+            self,
+            wait,
+            |r| &r.header().ev,
+            |r| !r.header().is_closed(),
+            Park::Waiter,
+            |r| match r.try_recv() {
+                Err(BroadcastError::Empty) => None,
+                r => Some(r),
+            },
+            || None,
+        )
+        .unwrap_or(Err(BroadcastError::Closed))
+    }
+}
+
+impl<T> Clone for BroadcastReceiver<'_, T> {
+    fn clone(&self) -> Self {
+        self.header().subscribers.fetch_add(1, Ordering::Relaxed);
+        Self {
+            raw: RawBroadcast {
+                header: self.raw.header,
+                buf: self.raw.buf,
+            },
+            next: self.next,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T> Drop for BroadcastReceiver<'_, T> {
+    fn drop(&mut self) {
+        self.header().subscribers.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(all(test, feature = "alloc", not(loom)))]
+mod tests {
+    use super::*;
+    use crate::uring::Spin;
+
+    #[test]
+    fn broadcast() {
+        let b = Broadcast::<u32>::new(4);
+        let mut tx = b.sender().unwrap();
+        assert!(b.sender().is_none());
+        let mut r1 = b.subscribe();
+        tx.send(0);
+        let mut r2 = tx.subscribe();
+        assert_eq!(tx.header().subscribers(), 2);
+
+        tx.send(1);
+        assert_eq!(r1.try_recv(), Ok(0));
+        assert_eq!(r1.try_recv(), Ok(1));
+        assert_eq!(r1.try_recv(), Err(BroadcastError::Empty));
+        // Subscribers only see entries sent after they subscribe.
+        assert_eq!(r2.try_recv(), Ok(1));
+
+        // A slow receiver lags behind instead of blocking the sender.
+        (2..10).for_each(|i| tx.send(i));
+        let mut r3 = r2.clone();
+        assert_eq!(r2.try_recv(), Err(BroadcastError::Lagged(5)));
+        assert_eq!(r2.try_recv(), Ok(7));
+        assert_eq!(r3.try_recv(), Err(BroadcastError::Lagged(5)));
+        drop(r3);
+        assert_eq!(tx.header().subscribers(), 2);
+
+        drop(tx);
+        assert_eq!(r2.try_recv(), Ok(8));
+        assert_eq!(r2.try_recv(), Ok(9));
+        assert_eq!(r2.try_recv(), Err(BroadcastError::Closed));
+        assert_eq!(r1.recv_blocking(&Spin), Err(BroadcastError::Lagged(5)));
+    }
+
+    #[test]
+    fn broadcast_blocking() {
+        let b = Broadcast::<u64>::new(8);
+        let mut tx = b.sender().unwrap();
+        let receivers = (0..4).map(|_| b.subscribe()).collect::<Vec<_>>();
+        std::thread::scope(|cx| {
+            for mut rx in receivers {
+                cx.spawn(move || {
+                    let mut last = None;
+                    loop {
+                        match rx.recv_blocking(&Spin) {
+                            Ok(i) => {
+                                // Entries are always received in order.
+                                assert!(last < Some(i));
+                                last = Some(i);
+                            },
+                            Err(BroadcastError::Lagged(_)) => {},
+                            Err(BroadcastError::Closed) => break,
+                            Err(BroadcastError::Empty) => unreachable!(),
+                        }
+                    }
+                    assert_eq!(last, Some(9999));
+                });
+            }
+            for i in 0..10000 {
+                tx.send(i);
+            }
+            drop(tx);
+        });
+    }
+}
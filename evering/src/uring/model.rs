@@ -0,0 +1,235 @@
+//! Exhaustive concurrency tests checked by [`loom`], which are only built under
+//! `cfg(loom)`:
+//!
+//! ```sh
+//! RUSTFLAGS="--cfg loom" cargo test -p evering --release --lib uring::model
+//! ```
+//!
+//! Entries are wrapped in [`Tracked`], so that reading an entry which is not
+//! properly published by the producer is reported as a data race.
+//!
+//! [`loom`]: https://docs.rs/loom
+
+use loom::cell::UnsafeCell;
+use loom::sync::Arc;
+use loom::thread;
+
+use super::sync::{AtomicU32, Ordering};
+use super::wait::{Event, Park};
+use super::{Builder, Spin, Uring, dealloc, dealloc_buffer};
+
+struct Tracked(UnsafeCell<usize>);
+
+impl Tracked {
+    fn new(val: usize) -> Self {
+        let cell = UnsafeCell::new(0);
+        cell.with_mut(|p| unsafe { *p = val });
+        Self(cell)
+    }
+
+    fn get(&self) -> usize {
+        self.0.with(|p| unsafe { *p })
+    }
+}
+
+fn send_spin<U: Uring>(uring: &mut U, val: U::A) {
+    let mut val = val;
+    while let Err(v) = uring.send(val) {
+        val = v;
+        thread::yield_now();
+    }
+}
+
+fn recv_spin<U: Uring>(uring: &mut U) -> U::B {
+    loop {
+        if let Some(val) = uring.recv() {
+            return val;
+        }
+        thread::yield_now();
+    }
+}
+
+#[test]
+fn send_recv() {
+    loom::model(|| {
+        let mut b = Builder::<Tracked, Tracked>::new();
+        b.size_a(2).size_b(2);
+        let (mut pa, mut pb) = b.build();
+
+        let t = thread::spawn(move || {
+            for i in 0..2 {
+                send_spin(&mut pa, Tracked::new(i));
+            }
+            assert_eq!(recv_spin(&mut pa).get(), 2);
+        });
+        for i in 0..2 {
+            assert_eq!(recv_spin(&mut pb).get(), i);
+        }
+        send_spin(&mut pb, Tracked::new(2));
+        t.join().unwrap();
+    });
+}
+
+#[test]
+fn bulk() {
+    loom::model(|| {
+        let mut b = Builder::<Tracked, ()>::new();
+        b.size_a(4);
+        let (mut pa, mut pb) = b.build();
+
+        let t = thread::spawn(move || {
+            let mut i = 0;
+            while i < 2 {
+                i += pa.send_bulk((i..2).map(Tracked::new));
+            }
+            let mut r = pa.reserve(1);
+            while r.capacity() == 0 {
+                drop(r);
+                thread::yield_now();
+                r = pa.reserve(1);
+            }
+            r.push(Tracked::new(2)).ok().unwrap();
+            r.commit();
+        });
+        let mut r = Vec::new();
+        while r.len() < 3 {
+            r.extend(pb.recv_bulk().map(|v| v.get()));
+            thread::yield_now();
+        }
+        assert_eq!(r, [0, 1, 2]);
+        t.join().unwrap();
+    });
+}
+
+#[test]
+fn mpsc() {
+    loom::model(|| {
+        let mut b = Builder::<Tracked, ()>::new();
+        b.size_a(2);
+        let (pa, mut pb) = b.build_mpsc();
+
+        let handles = (0..2)
+            .map(|i| {
+                let tx = pa.sender_handle();
+                thread::spawn(move || {
+                    let mut val = Tracked::new(i);
+                    while let Err(v) = tx.send(val) {
+                        val = v;
+                        thread::yield_now();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut r = [recv_spin(&mut pb).get(), recv_spin(&mut pb).get()];
+        r.sort();
+        assert_eq!(r, [0, 1]);
+        handles.into_iter().for_each(|t| t.join().unwrap());
+        drop(pa);
+    });
+}
+
+#[test]
+fn mpsc_full() {
+    loom::model(|| {
+        let mut b = Builder::<Tracked, ()>::new();
+        b.size_a(2);
+        let (mut pa, mut pb) = b.build_mpsc();
+        // Producers race for the only slot while the consumer frees it.
+        pa.send(Tracked::new(0)).ok().unwrap();
+
+        let handles = (1..3)
+            .map(|i| {
+                let tx = pa.sender_handle();
+                thread::spawn(move || tx.send(Tracked::new(i)).is_ok().then_some(i))
+            })
+            .collect::<Vec<_>>();
+        let first = pb.recv().map(|v| v.get());
+        let sent = handles
+            .into_iter()
+            .filter_map(|t| t.join().unwrap())
+            .collect::<Vec<_>>();
+        // At most one slot is freed, and no entry is overwritten.
+        let r = first.into_iter().chain(pb.recv_bulk().map(|v| v.get()));
+        assert_eq!(r.collect::<Vec<_>>(), [[0].as_slice(), &sent].concat());
+        assert!(sent.len() <= first.is_some() as usize);
+        drop(pa);
+    });
+}
+
+#[test]
+fn blocking() {
+    loom::model(|| {
+        let (mut pa, mut pb) = Builder::<Tracked, ()>::new().build();
+
+        let t = thread::spawn(move || {
+            pa.send_blocking(Tracked::new(1), &Spin).ok().unwrap();
+        });
+        assert_eq!(pb.recv_blocking(&Spin).map(|v| v.get()), Some(1));
+        // The receiver is woken up once the sender is gone.
+        assert!(pb.recv_blocking(&Spin).is_none());
+        t.join().unwrap();
+    });
+}
+
+#[test]
+fn park_handshake() {
+    loom::model(|| {
+        let ev = Arc::new(Event::new());
+        let tail = Arc::new(AtomicU32::new(0));
+
+        let t = thread::spawn({
+            let (ev, tail) = (ev.clone(), tail.clone());
+            move || {
+                tail.store(1, Ordering::Release);
+                ev.has_waiters()
+            }
+        });
+        Park::Waiter.announce(&ev);
+        let ready = tail.load(Ordering::Acquire) != 0;
+        let woken = t.join().unwrap();
+        // Otherwise the waiter parks and nobody wakes it up.
+        assert!(ready || woken);
+    });
+}
+
+#[test]
+fn drop_with_entries() {
+    loom::model(|| {
+        let rc = Arc::new(());
+        let mut b = Builder::<Arc<()>, Arc<()>>::new();
+        b.size_a(2).size_b(2);
+        let (mut pa, mut pb) = b.build();
+        pa.send(rc.clone()).unwrap();
+        pb.send(rc.clone()).unwrap();
+
+        let t = thread::spawn(move || drop(pa));
+        drop(pb);
+        t.join().unwrap();
+        assert_eq!(Arc::strong_count(&rc), 1);
+    });
+}
+
+#[test]
+fn dispose() {
+    loom::model(|| {
+        let rc = Arc::new(());
+        let (mut pa, pb) = Builder::<Arc<()>, ()>::new().build();
+        pa.send(rc.clone()).unwrap();
+
+        let t = thread::spawn(move || pa.dispose_raw().ok());
+        let rb = pb.dispose_raw().ok();
+        let ra = t.join().unwrap();
+        // Exactly one side is responsible for deallocating the memory.
+        let raw = match (ra, rb) {
+            (Some(raw), None) | (None, Some(raw)) => raw,
+            _ => panic!("both sides disposed the uring"),
+        };
+        assert_eq!(Arc::strong_count(&rc), 1);
+        unsafe {
+            let h = raw.header.as_ref();
+            dealloc_buffer(raw.buf_a, h.size_a());
+            dealloc_buffer(raw.buf_b, h.size_b());
+            dealloc(raw.header);
+        }
+    });
+}
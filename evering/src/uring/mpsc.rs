@@ -0,0 +1,284 @@
+use alloc::sync::Arc;
+
+use super::{Header, Queue, Reserve, Uring, WriteRecord, private};
+
+/// A [`Uring`] whose sending queue accepts multiple producers.
+///
+/// [`UringMpsc`] itself is the only consumer of the connection, while any
+/// number of [`MpscSender`]s can be created by
+/// [`sender_handle`](Self::sender_handle) and moved to other threads.
+pub struct UringMpsc<U: Uring>(Arc<Shared<U>>);
+
+/// A cloneable handle to submit entries to a [`UringMpsc`].
+pub struct MpscSender<U: Uring>(Arc<Shared<U>>);
+
+struct Shared<U>(U);
+
+// SAFETY: All producers go through the multi-producer path of the sending
+// queue, and the receiving queue is only accessed by the unique `UringMpsc`.
+unsafe impl<U: Uring + Send> Sync for Shared<U>
+where
+    U::A: Send,
+    U::Ext: Sync,
+{
+}
+
+impl<U: Uring> UringMpsc<U> {
+    pub fn new(uring: U) -> Self {
+        // SAFETY: We own the only producer of `uring`.
+        unsafe { uring.sender().init_mp() };
+        Self(Arc::new(Shared(uring)))
+    }
+
+    /// Creates a new handle to submit entries from other threads.
+    pub fn sender_handle(&self) -> MpscSender<U> {
+        MpscSender(self.0.clone())
+    }
+
+    /// Returns the underlying [`Uring`] if there is no alive [`MpscSender`].
+    pub fn try_into_inner(self) -> Result<U, Self> {
+        Arc::try_unwrap(self.0)
+            .map(|Shared(uring)| uring)
+            .map_err(Self)
+    }
+}
+
+impl<U: Uring> private::Sealed for UringMpsc<U> {}
+impl<U: Uring> Uring for UringMpsc<U> {
+    type A = U::A;
+    type B = U::B;
+    type Ext = U::Ext;
+
+    fn header(&self) -> &Header<Self::Ext> {
+        self.0.0.header()
+    }
+
+    fn sender_lane(&self, lane: usize) -> Option<Queue<Self::A>> {
+        self.0.0.sender_lane(lane)
+    }
+
+    fn receiver_lane(&self, lane: usize) -> Option<Queue<Self::B>> {
+        self.0.0.receiver_lane(lane)
+    }
+
+    fn send(&mut self, val: Self::A) -> Result<(), Self::A> {
+        unsafe { self.sender().enqueue_mp(val) }
+    }
+
+    fn send_bulk<I>(&mut self, vals: I) -> usize
+    where
+        I: Iterator<Item = Self::A>,
+    {
+        unsafe { enqueue_bulk_locked(self.sender(), vals) }
+    }
+
+    /// Reserves at most `n` slots in the sending queue.
+    ///
+    /// Other producers are blocked from sending until the returned [`Reserve`]
+    /// is dropped. No slot is reserved if there is another batch in progress.
+    fn reserve(&mut self, n: usize) -> Reserve<Self::A> {
+        unsafe { reserve_locked(self.sender(), n) }
+    }
+
+    /// Reserves a record in the sending queue.
+    ///
+    /// Other producers are blocked from sending until the returned
+    /// [`WriteRecord`] is dropped. It returns [`None`] if there is another
+    /// batch in progress.
+    fn write_record(&mut self, len: usize) -> Option<WriteRecord>
+    where
+        Self: Uring<A = u8>,
+    {
+        unsafe { write_record_locked(self.sender(), len) }
+    }
+}
+
+impl<U: Uring> MpscSender<U> {
+    pub fn header(&self) -> &Header<U::Ext> {
+        self.0.0.header()
+    }
+
+    pub fn ext(&self) -> &U::Ext
+    where
+        U::Ext: Sync,
+    {
+        self.0.0.ext()
+    }
+
+    /// Returns `true` if the remote [`Uring`] is not dropped.
+    pub fn is_connected(&self) -> bool {
+        self.0.0.is_connected()
+    }
+
+    pub fn send(&self, val: U::A) -> Result<(), U::A> {
+        unsafe { self.0.0.sender().enqueue_mp(val) }
+    }
+
+    /// Sends entries in a batch.
+    ///
+    /// Other producers are blocked from sending until this function returns.
+    /// It returns `0` if there is another batch in progress.
+    pub fn send_bulk<I>(&self, vals: I) -> usize
+    where
+        I: Iterator<Item = U::A>,
+    {
+        unsafe { enqueue_bulk_locked(self.0.0.sender(), vals) }
+    }
+
+    /// Reserves at most `n` slots in the sending queue.
+    ///
+    /// See [`UringMpsc::reserve`] for more information.
+    pub fn reserve(&self, n: usize) -> Reserve<U::A> {
+        unsafe { reserve_locked(self.0.0.sender(), n) }
+    }
+
+    /// Reserves a record in the sending queue.
+    ///
+    /// See [`UringMpsc::write_record`] for more information.
+    pub fn write_record(&self, len: usize) -> Option<WriteRecord>
+    where
+        U: Uring<A = u8>,
+    {
+        unsafe { write_record_locked(self.0.0.sender(), len) }
+    }
+}
+
+impl<U: Uring> Clone for MpscSender<U> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+unsafe fn enqueue_bulk_locked<T>(mut queue: Queue<T>, vals: impl Iterator<Item = T>) -> usize {
+    unsafe {
+        if !queue.lock_mp() {
+            return 0;
+        }
+        let n = queue.enqueue_bulk(vals);
+        queue.unlock_mp();
+        n
+    }
+}
+
+unsafe fn reserve_locked<T>(queue: Queue<T>, n: usize) -> Reserve<T> {
+    unsafe {
+        let locked = queue.lock_mp();
+        queue.reserve(if locked { n } else { 0 }, locked)
+    }
+}
+
+unsafe fn write_record_locked(queue: Queue<u8>, len: usize) -> Option<WriteRecord> {
+    unsafe {
+        if !queue.lock_mp() {
+            return None;
+        }
+        queue.write_record(len, true)
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use crate::uring::Builder;
+    use crate::uring::sync::Ordering;
+
+    #[test]
+    fn uring_mpsc() {
+        const PRODUCERS: usize = 4;
+        const PER_PRODUCER: usize = 100;
+
+        let (pa, mut pb) = Builder::<(usize, usize), ()>::new().build_mpsc();
+        std::thread::scope(|cx| {
+            for p in 0..PRODUCERS {
+                let tx = pa.sender_handle();
+                cx.spawn(move || {
+                    let mut i = 0;
+                    while i < PER_PRODUCER {
+                        if i % 10 == 0 {
+                            i += tx.send_bulk((i..PER_PRODUCER).map(|i| (p, i)).take(5));
+                        } else if tx.send((p, i)).is_ok() {
+                            i += 1;
+                        }
+                        std::thread::yield_now();
+                    }
+                });
+            }
+            cx.spawn(|| {
+                let mut last = [None; PRODUCERS];
+                let mut n = 0;
+                while n < PRODUCERS * PER_PRODUCER {
+                    while let Some((p, i)) = pb.recv() {
+                        // Entries from the same producer must remain ordered.
+                        assert_eq!(last[p].map_or(0, |l| l + 1), i);
+                        last[p] = Some(i);
+                        n += 1;
+                    }
+                    std::thread::yield_now();
+                }
+            });
+        });
+        assert!(pa.try_into_inner().is_ok());
+    }
+
+    #[test]
+    fn uring_mpsc_claim() {
+        let mut b = Builder::<usize, ()>::new();
+        b.size_a(4);
+        let (pa, mut pb) = b.build_mpsc();
+        let tx = pa.sender_handle();
+        let claim = || pa.0.0.sender().off.prod.claim.load(Ordering::Relaxed);
+
+        // Claims never repeat across exclusive sends, or a stalled producer
+        // may succeed against a stale one.
+        for i in 1..=8 {
+            assert_eq!(tx.send_bulk(0..2), 2);
+            tx.send(2).unwrap();
+            assert_eq!(pb.recv_bulk().count(), 3);
+            assert_eq!(claim(), i * 3);
+        }
+    }
+
+    #[test]
+    fn uring_mpsc_record() {
+        const PRODUCERS: u8 = 4;
+        const PER_PRODUCER: u8 = 100;
+
+        let mut b = Builder::<u8, ()>::new();
+        b.size_a(64);
+        let (pa, mut pb) = b.build_mpsc();
+        std::thread::scope(|cx| {
+            for p in 0..PRODUCERS {
+                let tx = pa.sender_handle();
+                cx.spawn(move || {
+                    let mut i = 0;
+                    while i < PER_PRODUCER {
+                        if let Some(mut r) = tx.write_record(2 + i as usize % 7) {
+                            r.fill(p);
+                            r[1] = i;
+                            r.commit();
+                            i += 1;
+                        }
+                        std::thread::yield_now();
+                    }
+                });
+            }
+            cx.spawn(|| {
+                let mut last = [None; PRODUCERS as usize];
+                let mut n = 0;
+                while n < PRODUCERS as usize * PER_PRODUCER as usize {
+                    while let Some(r) = pb.read_record().unwrap() {
+                        let (p, i) = (r[0], r[1]);
+                        assert_eq!(r.len(), 2 + i as usize % 7);
+                        assert!(r[2..].iter().all(|&b| b == p));
+                        // Records from the same producer must remain ordered.
+                        assert_eq!(last[p as usize].map_or(0, |l| l + 1), i);
+                        last[p as usize] = Some(i);
+                        n += 1;
+                    }
+                    std::thread::yield_now();
+                }
+            });
+        });
+        assert!(pa.try_into_inner().is_ok());
+    }
+}
@@ -0,0 +1,337 @@
+use alloc::collections::VecDeque;
+use core::mem::{self, ManuallyDrop};
+use core::ptr;
+
+use super::sync::Ordering;
+use super::{Drain, Header, Queue, RecvGuard, Reserve, Uring, WaitStrategy, private, wait};
+
+/// A [`Uring`] which never fails to send.
+///
+/// Entries that do not fit in the sending queue are spilled to a local
+/// overflow list, and flushed in order on the next [`send`](Uring::send) or
+/// [`recv`](Uring::recv). The remote side can find pending overflowed entries
+/// through [`Uring::peer_overflowed`].
+///
+/// Overflowed entries are flushed as far as possible when this
+/// [`UringOverflow`] is dropped, and those which still do not fit are dropped
+/// as well. Use [`flush_blocking`](Self::flush_blocking) to make sure they are
+/// delivered, or [`into_inner`](Self::into_inner) to take them back.
+pub struct UringOverflow<U: Uring> {
+    uring: U,
+    overflow: VecDeque<U::A>,
+}
+
+impl<U: Uring> UringOverflow<U> {
+    pub fn new(uring: U) -> Self {
+        Self {
+            uring,
+            overflow: VecDeque::new(),
+        }
+    }
+
+    /// Returns the number of overflowed entries.
+    pub fn overflowed(&self) -> usize {
+        self.overflow.len()
+    }
+
+    /// Sends as many overflowed entries as possible, and returns `true` if
+    /// there is no more overflowed entry.
+    pub fn flush(&mut self) -> bool {
+        if self.overflow.is_empty() {
+            return true;
+        }
+        let overflow = &mut self.overflow;
+        self.uring
+            .send_bulk(core::iter::from_fn(|| overflow.pop_front()));
+        if self.overflow.is_empty() {
+            self.set_overflowed(false);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Flushes overflowed entries, waiting for free slots with the given
+    /// strategy.
+    ///
+    /// It returns `false` if the remote side is disconnected before all
+    /// entries are sent.
+    pub fn flush_blocking<W: WaitStrategy>(&mut self, wait: &W) -> bool {
+        wait::wait_for(
+            self,
+            wait,
+            |u| &u.sender().ev.writable,
+            |u| u.flush().then_some(()),
+            || None,
+        )
+        .is_some()
+    }
+
+    /// Drops all overflowed entries.
+    pub fn clear_overflow(&mut self) {
+        self.overflow.clear();
+        self.set_overflowed(false);
+    }
+
+    /// Returns a reference to the underlying [`Uring`].
+    pub fn get_ref(&self) -> &U {
+        &self.uring
+    }
+
+    /// Returns the underlying [`Uring`] along with overflowed entries.
+    pub fn into_inner(self) -> (U, VecDeque<U::A>) {
+        let mut this = ManuallyDrop::new(self);
+        let overflow = mem::take(&mut this.overflow);
+        // SAFETY: `this` is never used or dropped again.
+        let uring = unsafe { ptr::read(&this.uring) };
+        (uring, overflow)
+    }
+
+    fn spill(&mut self, val: U::A) {
+        if self.overflow.is_empty() {
+            self.set_overflowed(true);
+        }
+        self.overflow.push_back(val);
+    }
+
+    fn set_overflowed(&self, overflowed: bool) {
+        let off = self.uring.sender().off;
+        off.prod
+            .overflow
+            .store(overflowed as u32, Ordering::Relaxed);
+    }
+
+    /// Receives an entry, waiting for new entries or, if there are overflowed
+    /// entries, free slots to flush them.
+    fn recv_with<W: WaitStrategy>(
+        &mut self,
+        wait: &W,
+        mut timeout: impl FnMut() -> Option<core::time::Duration>,
+    ) -> Option<U::B> {
+        loop {
+            if self.flush() {
+                return wait::wait_for(
+                    self,
+                    wait,
+                    |u| &u.receiver().ev.readable,
+                    Self::recv,
+                    &mut timeout,
+                );
+            }
+            // The remote side may wait for overflowed entries without sending
+            // anything, so we have to flush them as soon as possible.
+            let r = wait::wait_for(
+                self,
+                wait,
+                |u| &u.sender().ev.writable,
+                |u| match u.uring.recv() {
+                    Some(val) => Some(Some(val)),
+                    None => u.flush().then_some(None),
+                },
+                &mut timeout,
+            )?;
+            if r.is_some() {
+                return r;
+            }
+        }
+    }
+}
+
+impl<U: Uring> Drop for UringOverflow<U> {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+impl<U: Uring> private::Sealed for UringOverflow<U> {}
+impl<U: Uring> Uring for UringOverflow<U> {
+    type A = U::A;
+    type B = U::B;
+    type Ext = U::Ext;
+
+    fn header(&self) -> &Header<Self::Ext> {
+        self.uring.header()
+    }
+
+    fn sender_lane(&self, lane: usize) -> Option<Queue<Self::A>> {
+        self.uring.sender_lane(lane)
+    }
+
+    fn receiver_lane(&self, lane: usize) -> Option<Queue<Self::B>> {
+        self.uring.receiver_lane(lane)
+    }
+
+    /// Sends an entry, or spills it to the overflow list if the sending queue
+    /// is full. It never fails.
+    fn send(&mut self, val: Self::A) -> Result<(), Self::A> {
+        if !self.flush() {
+            self.spill(val);
+        } else if let Err(val) = self.uring.send(val) {
+            self.spill(val);
+        }
+        Ok(())
+    }
+
+    /// Sends all entries, spilling those that do not fit to the overflow
+    /// list. It always returns the number of given entries.
+    fn send_bulk<I>(&mut self, vals: I) -> usize
+    where
+        I: Iterator<Item = Self::A>,
+    {
+        let mut vals = vals;
+        let mut n = 0;
+        if self.flush() {
+            n += self.uring.send_bulk(vals.by_ref());
+        }
+        for val in vals {
+            self.spill(val);
+            n += 1;
+        }
+        n
+    }
+
+    /// Reserves at most `n` slots in the sending queue.
+    ///
+    /// No slot is reserved if there are overflowed entries.
+    fn reserve(&mut self, n: usize) -> Reserve<Self::A> {
+        let n = if self.flush() { n } else { 0 };
+        self.uring.reserve(n)
+    }
+
+    fn recv(&mut self) -> Option<Self::B> {
+        self.flush();
+        self.uring.recv()
+    }
+
+    fn recv_bulk(&mut self) -> Drain<Self::B> {
+        self.flush();
+        self.uring.recv_bulk()
+    }
+
+    fn peek(&mut self) -> Option<&Self::B> {
+        self.flush();
+        self.uring.peek()
+    }
+
+    fn recv_guard(&mut self) -> Option<RecvGuard<Self::B>> {
+        self.flush();
+        self.uring.recv_guard()
+    }
+
+    fn recv_blocking<W: WaitStrategy>(&mut self, wait: &W) -> Option<Self::B> {
+        self.recv_with(wait, || None)
+    }
+
+    #[cfg(feature = "std")]
+    fn recv_timeout<W: WaitStrategy>(
+        &mut self,
+        wait: &W,
+        timeout: core::time::Duration,
+    ) -> Option<Self::B> {
+        let deadline = std::time::Instant::now() + timeout;
+        self.recv_with(wait, || {
+            Some(deadline.saturating_duration_since(std::time::Instant::now()))
+        })
+    }
+
+    /// Receives an entry like [`Uring::recv_polling`], or falls back to
+    /// [`recv_timeout`](Uring::recv_timeout) if there are overflowed entries
+    /// waiting for free slots.
+    #[cfg(feature = "std")]
+    fn recv_polling<W: WaitStrategy>(
+        &mut self,
+        wait: &W,
+        idle: core::time::Duration,
+        timeout: core::time::Duration,
+    ) -> Option<Self::B> {
+        if self.flush() {
+            self.uring.recv_polling(wait, idle, timeout)
+        } else {
+            self.recv_timeout(wait, timeout)
+        }
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use crate::uring::{Builder, Spin};
+
+    #[test]
+    fn uring_overflow() {
+        let mut b = Builder::<usize, ()>::new();
+        b.size_a(4);
+        let (pa, mut pb) = b.build();
+        let mut pa = UringOverflow::new(pa);
+
+        // A queue of size 4 holds at most 3 entries.
+        for i in 0..5 {
+            pa.send(i).unwrap();
+        }
+        assert_eq!(pa.send_bulk(5..8), 3);
+        assert_eq!(pa.overflowed(), 5);
+        assert!(pb.peer_overflowed());
+        assert_eq!(pa.reserve(1).capacity(), 0);
+
+        // Entries are flushed in order as the remote side catches up.
+        let mut r = pb.recv_bulk().collect::<Vec<_>>();
+        while !pa.flush() {
+            r.extend(pb.recv_bulk());
+        }
+        assert!(!pb.peer_overflowed());
+        r.extend(pb.recv_bulk());
+        assert_eq!(r, (0..8).collect::<Vec<_>>());
+
+        std::thread::scope(|cx| {
+            cx.spawn(|| {
+                for i in 0..64 {
+                    pa.send(i).unwrap();
+                }
+                // Wait for overflowed entries while there is nothing to receive.
+                assert_eq!(pa.recv_blocking(&Spin), None);
+            });
+            cx.spawn(move || {
+                for i in 0..64 {
+                    assert_eq!(pb.recv_blocking(&Spin), Some(i));
+                }
+                drop(pb);
+            });
+        });
+    }
+
+    #[test]
+    fn uring_overflow_drop() {
+        let mut b = Builder::<usize, ()>::new();
+        b.size_a(4);
+        let (pa, mut pb) = b.build();
+        let mut pa = UringOverflow::new(pa);
+        pa.send_bulk(0..5);
+        let (pa, overflow) = pa.into_inner();
+        assert_eq!(overflow, [3, 4]);
+
+        // Overflowed entries are flushed on drop if there are free slots.
+        let mut pa = UringOverflow::new(pa);
+        pb.recv_bulk().for_each(drop);
+        pa.send_bulk(0..5);
+        assert_eq!(pa.overflowed(), 2);
+        assert_eq!(pb.recv_bulk().count(), 3);
+        drop(pa);
+        assert_eq!(pb.recv_bulk().collect::<Vec<_>>(), [3, 4]);
+    }
+
+    #[test]
+    fn uring_overflow_drop_unflushed() {
+        let rc = std::rc::Rc::new(());
+        let mut b = Builder::<std::rc::Rc<()>, ()>::new();
+        b.size_a(2);
+        let (pa, pb) = b.build();
+        let mut pa = UringOverflow::new(pa);
+        pa.send_bulk(core::iter::repeat_n(rc.clone(), 3));
+
+        // Entries which do not fit are dropped while the remote side is alive.
+        drop(pa);
+        assert_eq!(std::rc::Rc::strong_count(&rc), 2);
+        drop(pb);
+        assert_eq!(std::rc::Rc::strong_count(&rc), 1);
+    }
+}
@@ -0,0 +1,85 @@
+use super::Uring;
+
+/// Detects whether the remote side of a [`Uring`] is still alive.
+///
+/// A [`Uring`] is only disconnected when the remote side drops it, which never
+/// happens if the remote process is killed. Instead, both sides are expected to
+/// call [`Uring::heartbeat`] periodically, and the remote side is considered
+/// dead once no heartbeat is observed within `max_missed` consecutive calls to
+/// [`check`](Self::check).
+///
+/// The interval of checks is up to the caller, and it should be long enough
+/// for the remote side to send at least one heartbeat in between.
+#[derive(Clone, Debug)]
+pub struct PeerMonitor {
+    last: Option<u32>,
+    missed: u32,
+    max_missed: u32,
+}
+
+impl PeerMonitor {
+    pub const fn new(max_missed: u32) -> Self {
+        Self {
+            last: None,
+            missed: 0,
+            max_missed,
+        }
+    }
+
+    /// Returns `false` if the remote side is disconnected or seems to be dead.
+    pub fn check<U: Uring + ?Sized>(&mut self, uring: &U) -> bool {
+        if !uring.is_connected() {
+            return false;
+        }
+        let beat = uring.peer_heartbeat();
+        if self.last == Some(beat) {
+            self.missed = self.missed.saturating_add(1);
+        } else {
+            self.last = Some(beat);
+            self.missed = 0;
+        }
+        self.missed < self.max_missed
+    }
+
+    /// Returns the number of consecutive checks without any heartbeat.
+    pub fn missed(&self) -> u32 {
+        self.missed
+    }
+}
+
+#[cfg(all(test, feature = "alloc", not(loom)))]
+mod tests {
+    use super::*;
+    use crate::uring::{Builder, dealloc, dealloc_buffer};
+
+    #[test]
+    fn peer_monitor() {
+        let (pa, pb) = Builder::<(), ()>::new().build();
+        let mut monitor = PeerMonitor::new(3);
+        for _ in 0..10 {
+            pb.heartbeat();
+            assert!(monitor.check(&pa));
+        }
+        // The remote side stops responding.
+        assert!(monitor.check(&pa));
+        assert!(monitor.check(&pa));
+        assert!(!monitor.check(&pa));
+        assert_eq!(monitor.missed(), 3);
+
+        pb.heartbeat();
+        assert!(monitor.check(&pa));
+        pa.close();
+        assert!(!monitor.check(&pa));
+        assert!(!pb.is_connected());
+
+        // Pretend that `pb` is gone without dropping.
+        core::mem::forget(pb);
+        unsafe {
+            let raw = pa.force_dispose_raw();
+            let h = raw.header.as_ref();
+            dealloc_buffer(raw.buf_a, h.size_a());
+            dealloc_buffer(raw.buf_b, h.size_b());
+            dealloc(raw.header);
+        }
+    }
+}
@@ -0,0 +1,295 @@
+use core::ptr::NonNull;
+
+use super::sync::Ordering;
+use super::{Offsets, Queue, RecordError};
+
+/// Records are placed at multiples of this alignment, so that a record header
+/// never wraps around the end of the buffer.
+const RECORD_ALIGN: u32 = size_of::<u32>() as u32;
+/// The smallest buffer which holds a record, below which nothing is read or
+/// written.
+const MIN_RECORD_BUFFER: u32 = 2 * RECORD_ALIGN;
+/// The header of a record which skips the rest of the buffer.
+const PADDING: u32 = u32::MAX;
+
+/// Returns the number of bytes taken by a record of `len` bytes.
+fn record_size(len: u32) -> Option<u32> {
+    len.checked_add(2 * RECORD_ALIGN - 1)
+        .map(|n| n & !(RECORD_ALIGN - 1))
+}
+
+unsafe fn read_header(buf: NonNull<u8>, pos: u32) -> u32 {
+    unsafe { buf.add(pos as usize).cast::<u32>().read_unaligned() }
+}
+
+unsafe fn write_header(buf: NonNull<u8>, pos: u32, header: u32) {
+    unsafe { buf.add(pos as usize).cast::<u32>().write_unaligned(header) }
+}
+
+impl<'a> Queue<'a, u8> {
+    /// Reserves a contiguous record of `len` bytes, placing it at the start of
+    /// the buffer if it does not fit in the rest.
+    ///
+    /// If `locked`, this queue has been locked by
+    /// [`lock_mp`](Self::lock_mp), and it is unlocked along with the record.
+    pub(super) unsafe fn write_record(self, len: usize, locked: bool) -> Option<WriteRecord<'a>> {
+        let Some((tail, pos)) = self.claim_record(len) else {
+            if locked {
+                unsafe { self.unlock_mp() };
+            }
+            return None;
+        };
+        Some(WriteRecord {
+            queue: self,
+            tail,
+            pos,
+            len: len as u32,
+            locked,
+        })
+    }
+
+    /// Returns the current tail and the position of a new record.
+    fn claim_record(&self, len: usize) -> Option<(u32, u32)> {
+        let off = self.off;
+        let size = off.size() as u32;
+        if size < MIN_RECORD_BUFFER {
+            return None;
+        }
+
+        let need = u32::try_from(len).ok().and_then(record_size)?;
+        let tail = off.prod.tail.load(Ordering::Relaxed);
+        if tail >= size || tail % RECORD_ALIGN != 0 {
+            return None;
+        }
+        let rest = size - tail;
+        let (pos, want) = if need <= rest {
+            (tail, need)
+        } else {
+            (0, rest.checked_add(need)?)
+        };
+        if off.free(tail, want) < want {
+            off.record_full();
+            return None;
+        }
+        Some((tail, pos))
+    }
+
+    /// Reads the record at the front of this queue.
+    ///
+    /// Indices and headers are written by the remote side, hence they are
+    /// validated before the payload is handed out.
+    pub(super) unsafe fn read_record(self) -> Result<Option<ReadRecord<'a>>, RecordError> {
+        let Self { off, buf, .. } = self;
+        let size = off.size() as u32;
+        let head = off.cons.head.load(Ordering::Relaxed);
+        let tail = off.tail(head);
+        if head == tail {
+            return Ok(None);
+        }
+        if size < MIN_RECORD_BUFFER || head >= size || head % RECORD_ALIGN != 0 {
+            return Err(RecordError {});
+        }
+        let (mut pos, mut skip) = (head, 0);
+        let mut len = unsafe { read_header(buf, pos) };
+        if len == PADDING {
+            // The padding is always published along with the next record.
+            (pos, skip) = (0, size - head);
+            len = unsafe { read_header(buf, pos) };
+        }
+        let used = record_size(len)
+            .filter(|&need| need <= size - pos)
+            .and_then(|need| need.checked_add(skip))
+            .ok_or(RecordError {})?;
+        // The cached tail may be outdated if the remote side publishes partial
+        // records.
+        let avail = |tail: u32| tail.wrapping_sub(head) & off.ring_mask;
+        if used > avail(tail) && used > avail(off.prod.tail.load(Ordering::Acquire)) {
+            return Err(RecordError {});
+        }
+        Ok(Some(ReadRecord {
+            queue: self,
+            head: pos,
+            len,
+        }))
+    }
+}
+
+/// A record reserved in a byte [`Queue`], created by
+/// [`Uring::write_record`](super::Uring::write_record).
+///
+/// The record is published by [`commit`](Self::commit), and discarded if this
+/// guard is dropped instead.
+pub struct WriteRecord<'a> {
+    queue: Queue<'a, u8>,
+    tail: u32,
+    /// Position of the record header, which is `0` if the rest of the buffer
+    /// is skipped.
+    pos: u32,
+    len: u32,
+    locked: bool,
+}
+
+impl WriteRecord<'_> {
+    /// Publishes this record.
+    pub fn commit(self) {
+        let Queue { off, ev, buf } = self.queue;
+        unsafe {
+            if self.pos != self.tail {
+                write_header(buf, self.tail, PADDING);
+            }
+            write_header(buf, self.pos, self.len);
+        }
+        let tail = next_pos(off, self.pos, self.len);
+        off.prod.tail.store(tail, Ordering::Release);
+        ev.readable.notify();
+        off.record_enqueued(1, tail);
+    }
+}
+
+impl Drop for WriteRecord<'_> {
+    fn drop(&mut self) {
+        if self.locked {
+            unsafe { self.queue.unlock_mp() }
+        }
+    }
+}
+
+impl core::ops::Deref for WriteRecord<'_> {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        unsafe { payload(self.queue.buf, self.pos, self.len).as_ref() }
+    }
+}
+
+impl core::ops::DerefMut for WriteRecord<'_> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { payload(self.queue.buf, self.pos, self.len).as_mut() }
+    }
+}
+
+/// A record at the front of a byte [`Queue`], created by
+/// [`Uring::read_record`](super::Uring::read_record).
+///
+/// The record is consumed when this guard is dropped, unless it is left in the
+/// queue by [`reject`](Self::reject).
+pub struct ReadRecord<'a> {
+    queue: Queue<'a, u8>,
+    head: u32,
+    len: u32,
+}
+
+impl ReadRecord<'_> {
+    /// Leaves the record in the queue, so that it will be read again.
+    pub fn reject(self) {
+        core::mem::forget(self);
+    }
+}
+
+impl core::ops::Deref for ReadRecord<'_> {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        unsafe { payload(self.queue.buf, self.head, self.len).as_ref() }
+    }
+}
+
+impl core::ops::DerefMut for ReadRecord<'_> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { payload(self.queue.buf, self.head, self.len).as_mut() }
+    }
+}
+
+impl Drop for ReadRecord<'_> {
+    fn drop(&mut self) {
+        let Queue { off, ev, .. } = self.queue;
+        off.cons
+            .head
+            .store(next_pos(off, self.head, self.len), Ordering::Release);
+        ev.writable.notify();
+        off.record_dequeued(1);
+    }
+}
+
+fn next_pos(off: &Offsets, pos: u32, len: u32) -> u32 {
+    pos.wrapping_add(record_size(len).unwrap()) & off.ring_mask
+}
+
+unsafe fn payload(buf: NonNull<u8>, pos: u32, len: u32) -> NonNull<[u8]> {
+    let data = unsafe { buf.add((pos + RECORD_ALIGN) as usize) };
+    NonNull::slice_from_raw_parts(data, len as usize)
+}
+
+#[cfg(all(test, feature = "alloc", not(loom)))]
+mod tests {
+    use super::super::{Builder, Uring};
+
+    #[test]
+    fn record_wrap() {
+        let mut b = Builder::<u8, u8>::new();
+        b.size_a(32);
+        let (mut pa, mut pb) = b.build();
+
+        for i in 0..16u8 {
+            let len = 1 + i as usize % 11;
+            let mut r = pa.write_record(len).unwrap();
+            r.fill(i);
+            r.commit();
+
+            let r = pb.read_record().unwrap().unwrap();
+            assert_eq!(&*r, &[i].repeat(len));
+        }
+        assert!(pb.read_record().unwrap().is_none());
+    }
+
+    #[test]
+    fn record_full() {
+        let mut b = Builder::<u8, u8>::new();
+        b.size_a(32);
+        let (mut pa, mut pb) = b.build();
+
+        // Only `size - 4` bytes can be used, and each record takes 4 more.
+        assert!(pa.write_record(25).is_none());
+        pa.write_record(12).unwrap().commit();
+        pa.write_record(8).unwrap().commit();
+        assert!(pa.write_record(1).is_none());
+        assert_eq!(pb.read_record().unwrap().unwrap().len(), 12);
+        // Dropped records are never published.
+        pa.write_record(4).unwrap().fill(0);
+
+        // The record does not fit in the rest, hence the padding.
+        pa.write_record(6).unwrap().commit();
+        assert!(pa.write_record(1).is_none());
+        pb.read_record().unwrap().unwrap().reject();
+        assert_eq!(pb.read_record().unwrap().unwrap().len(), 8);
+        assert_eq!(pb.read_record().unwrap().unwrap().len(), 6);
+        assert!(pb.read_record().unwrap().is_none());
+    }
+
+    #[test]
+    fn record_malformed() {
+        let mut b = Builder::<u8, u8>::new();
+        b.size_a(32);
+        let (mut pa, mut pb) = b.build();
+
+        // The length exceeds the published bytes.
+        pa.send_bulk(4u32.to_ne_bytes().into_iter());
+        assert!(pb.read_record().is_err());
+        pa.send_bulk([0; 4].into_iter());
+        assert_eq!(pb.read_record().unwrap().unwrap().len(), 4);
+
+        // The length exceeds the buffer.
+        pa.send_bulk(u32::MAX.wrapping_sub(1).to_ne_bytes().into_iter());
+        assert!(pb.read_record().is_err());
+    }
+
+    #[test]
+    fn record_small_buffer() {
+        let mut b = Builder::<u8, u8>::new();
+        b.size_a(2);
+        let (mut pa, mut pb) = b.build();
+
+        // The buffer cannot even hold a record header.
+        assert!(pa.write_record(0).is_none());
+        pa.send(1).unwrap();
+        assert!(pb.read_record().is_err());
+    }
+}
@@ -0,0 +1,75 @@
+use core::cell::UnsafeCell;
+use core::marker::PhantomData;
+use core::mem::MaybeUninit;
+use core::num::NonZero;
+use core::sync::atomic::{
+    AtomicBool, AtomicI8, AtomicI16, AtomicI32, AtomicI64, AtomicU8, AtomicU16, AtomicU32,
+    AtomicU64,
+};
+
+#[cfg(feature = "derive")]
+pub use evering_macros::ShmSafe;
+
+/// Types that can be safely shared between processes.
+///
+/// Values of such types are meaningful in any address space, and have the
+/// same size on any architecture. Pointers, references and anything that owns
+/// heap memory are excluded, so are `usize` and `isize` whose sizes vary by
+/// architecture.
+///
+/// Alignments are not fixed, though. 64-bit and 128-bit integers, floats and
+/// atomics may be less aligned on 32-bit targets, and the alignment of
+/// `u128`/`i128` has changed between compiler versions. Processes sharing
+/// such types should be built for the same target by compatible compilers,
+/// which [`Header::check_layout`] verifies for entries of a [`Uring`].
+///
+/// [`Header::check_layout`]: super::Header::check_layout
+/// [`Uring`]: super::Uring
+///
+/// With the `derive` feature, this trait can be derived for types with a
+/// stable layout, e.g. `#[repr(C)]`, whose fields are all [`ShmSafe`].
+///
+/// # Safety
+///
+/// The implementor must contain no pointer to memory private to a process,
+/// must not contain any architecture dependent field, and must have a layout
+/// specified by its representation rather than chosen by the compiler.
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` cannot be shared between processes",
+    note = "pointers, references, heap allocated types and `usize`/`isize` are not `ShmSafe`"
+)]
+pub unsafe trait ShmSafe {}
+
+macro_rules! impl_shm_safe {
+    ($($ty:ty),* $(,)?) => {
+        $(unsafe impl ShmSafe for $ty {})*
+    };
+}
+
+impl_shm_safe! {
+    (), bool, char, f32, f64,
+    u8, u16, u32, u64, u128,
+    i8, i16, i32, i64, i128,
+    AtomicBool,
+    AtomicU8, AtomicU16, AtomicU32, AtomicU64,
+    AtomicI8, AtomicI16, AtomicI32, AtomicI64,
+}
+
+// `Option<NonZero<_>>` is guaranteed to have the same layout as the integer,
+// while the layout of other `Option`s and tuples is unspecified.
+macro_rules! impl_shm_safe_nonzero {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            unsafe impl ShmSafe for NonZero<$ty> {}
+            unsafe impl ShmSafe for Option<NonZero<$ty>> {}
+        )*
+    };
+}
+
+impl_shm_safe_nonzero!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+unsafe impl<T: ?Sized> ShmSafe for PhantomData<T> {}
+unsafe impl<T: ShmSafe> ShmSafe for MaybeUninit<T> {}
+unsafe impl<T: ShmSafe + ?Sized> ShmSafe for UnsafeCell<T> {}
+unsafe impl<T: ShmSafe, const N: usize> ShmSafe for [T; N] {}
+unsafe impl<T: ShmSafe> ShmSafe for [T] {}
@@ -0,0 +1,175 @@
+use core::cell::UnsafeCell;
+use core::marker::PhantomData;
+use core::mem::MaybeUninit;
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use super::{CLAIM_LOCKED, Header, Queue, RawUring, SIDE_A, SIDE_B, Uring, private};
+
+/// A [`Uring`] in statically allocated memory, whose queues hold at most
+/// `N - 1` entries of `A` and `M - 1` entries of `B`.
+///
+/// It can be created in const contexts and placed in a `static`, so that no
+/// allocator is required. Both sides of the connection are obtained through
+/// [`split`](Self::split), which borrow this [`StaticUring`] and never
+/// deallocate its memory.
+pub struct StaticUring<A, B, const N: usize, const M: usize, Ext = ()> {
+    header: Header<Ext>,
+    buf_a: UnsafeCell<[MaybeUninit<A>; N]>,
+    buf_b: UnsafeCell<[MaybeUninit<B>; M]>,
+    split: AtomicBool,
+}
+
+unsafe impl<A: Send, B: Send, const N: usize, const M: usize, Ext: Send + Sync> Sync
+    for StaticUring<A, B, N, M, Ext>
+{
+}
+
+impl<A, B, const N: usize, const M: usize> StaticUring<A, B, N, M> {
+    pub const fn new() -> Self {
+        Self::new_ext(())
+    }
+}
+
+impl<A, B, const N: usize, const M: usize, Ext> StaticUring<A, B, N, M, Ext> {
+    pub const fn new_ext(ext: Ext) -> Self {
+        const {
+            assert!(N.is_power_of_two() && N <= CLAIM_LOCKED as usize);
+            assert!(M.is_power_of_two() && M <= CLAIM_LOCKED as usize);
+        }
+        Self {
+            header: Header::new_static::<A, B>(N as u32, M as u32, ext),
+            buf_a: UnsafeCell::new([const { MaybeUninit::uninit() }; N]),
+            buf_b: UnsafeCell::new([const { MaybeUninit::uninit() }; M]),
+            split: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns both sides of the connection, or [`None`] if this
+    /// [`StaticUring`] has been split before.
+    pub fn split(&self) -> Option<(StaticUringA<'_, A, B, Ext>, StaticUringB<'_, A, B, Ext>)> {
+        if self.split.swap(true, Ordering::Relaxed) {
+            return None;
+        }
+        let raw = || RawUring {
+            header: NonNull::from(&self.header),
+            buf_a: NonNull::new(self.buf_a.get()).unwrap().cast(),
+            buf_b: NonNull::new(self.buf_b.get()).unwrap().cast(),
+            marker: PhantomData,
+        };
+        Some((
+            StaticUringA(raw(), PhantomData),
+            StaticUringB(raw(), PhantomData),
+        ))
+    }
+}
+
+impl<A, B, const N: usize, const M: usize> Default for StaticUring<A, B, N, M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The A side of a [`StaticUring`].
+pub struct StaticUringA<'a, A, B, Ext = ()>(RawUring<A, B, Ext>, PhantomData<&'a ()>);
+/// The B side of a [`StaticUring`].
+pub struct StaticUringB<'a, A, B, Ext = ()>(RawUring<A, B, Ext>, PhantomData<&'a ()>);
+
+unsafe impl<A: Send, B: Send, Ext: Send + Sync> Send for StaticUringA<'_, A, B, Ext> {}
+unsafe impl<A: Send, B: Send, Ext: Send + Sync> Send for StaticUringB<'_, A, B, Ext> {}
+
+impl<A, B, Ext> private::Sealed for StaticUringA<'_, A, B, Ext> {}
+impl<A, B, Ext> Uring for StaticUringA<'_, A, B, Ext> {
+    type A = A;
+    type B = B;
+    type Ext = Ext;
+
+    fn header(&self) -> &Header<Ext> {
+        unsafe { self.0.header() }
+    }
+    fn sender_lane(&self, lane: usize) -> Option<Queue<Self::A>> {
+        unsafe { self.0.queue_a(lane) }
+    }
+    fn receiver_lane(&self, lane: usize) -> Option<Queue<Self::B>> {
+        unsafe { self.0.queue_b(lane) }
+    }
+}
+
+impl<A, B, Ext> private::Sealed for StaticUringB<'_, A, B, Ext> {}
+impl<A, B, Ext> Uring for StaticUringB<'_, A, B, Ext> {
+    type A = B;
+    type B = A;
+    type Ext = Ext;
+
+    fn header(&self) -> &Header<Ext> {
+        unsafe { self.0.header() }
+    }
+    fn sender_lane(&self, lane: usize) -> Option<Queue<Self::A>> {
+        unsafe { self.0.queue_b(lane) }
+    }
+    fn receiver_lane(&self, lane: usize) -> Option<Queue<Self::B>> {
+        unsafe { self.0.queue_a(lane) }
+    }
+}
+
+// Enqueued entries are dropped along with the last side, while the memory
+// itself is owned by the `StaticUring`.
+impl<A, B, Ext> Drop for StaticUringA<'_, A, B, Ext> {
+    fn drop(&mut self) {
+        _ = unsafe { self.0.dispose(SIDE_A) };
+    }
+}
+
+impl<A, B, Ext> Drop for StaticUringB<'_, A, B, Ext> {
+    fn drop(&mut self) {
+        _ = unsafe { self.0.dispose(SIDE_B) };
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+
+    #[test]
+    fn static_uring() {
+        static URING: StaticUring<i32, i32, 4, 8> = StaticUring::new();
+
+        let (mut pa, mut pb) = URING.split().unwrap();
+        assert!(URING.split().is_none());
+        assert_eq!(pa.header().size_a(), 4);
+        assert_eq!(pb.header().size_b(), 8);
+
+        assert_eq!(pa.send_bulk(0..10), 3);
+        assert_eq!(pb.recv_bulk().collect::<Vec<_>>(), [0, 1, 2]);
+        assert_eq!(pb.send_bulk(0..10), 7);
+        assert_eq!(pa.recv(), Some(0));
+
+        drop(pa);
+        assert!(!pb.is_connected());
+    }
+
+    #[test]
+    fn static_uring_drop() {
+        struct Counted<'a>(&'a AtomicUsize);
+        impl Drop for Counted<'_> {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let dropped = AtomicUsize::new(0);
+        let uring = StaticUring::<Counted, (), 4, 4>::new();
+        let (mut pa, pb) = uring.split().unwrap();
+        assert_eq!(
+            pa.send_bulk(std::iter::repeat_with(|| Counted(&dropped))),
+            3
+        );
+        drop(pb);
+        assert_eq!(dropped.load(Ordering::Relaxed), 0);
+        // Entries are dropped along with the last side.
+        drop(pa);
+        assert_eq!(dropped.load(Ordering::Relaxed), 3);
+    }
+}
@@ -0,0 +1,151 @@
+use super::Queue;
+use super::sync::{AtomicU32, AtomicU64, Ordering, const_fn};
+
+/// Counters updated by producers of a queue.
+#[repr(C)]
+pub(super) struct ProducerStats {
+    enqueued: AtomicU64,
+    full: AtomicU64,
+    peak: AtomicU32,
+}
+
+/// Counters updated by the consumer of a queue.
+#[repr(C)]
+pub(super) struct ConsumerStats {
+    dequeued: AtomicU64,
+    empty: AtomicU64,
+}
+
+impl ProducerStats {
+    const_fn! {
+        pub fn new() -> Self {
+            Self {
+                enqueued: AtomicU64::new(0),
+                full: AtomicU64::new(0),
+                peak: AtomicU32::new(0),
+            }
+        }
+    }
+
+    #[inline]
+    pub fn enqueued(&self, n: u32, len: u32) {
+        self.enqueued.fetch_add(n as u64, Ordering::Relaxed);
+        self.peak.fetch_max(len, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn full(&self) {
+        self.full.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl ConsumerStats {
+    const_fn! {
+        pub fn new() -> Self {
+            Self {
+                dequeued: AtomicU64::new(0),
+                empty: AtomicU64::new(0),
+            }
+        }
+    }
+
+    #[inline]
+    pub fn dequeued(&self, n: u32) {
+        self.dequeued.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn empty(&self) {
+        self.empty.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Traffic counters of all lanes in one direction of a [`Uring`].
+///
+/// [`Uring`]: super::Uring
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct QueueStats {
+    /// Number of entries enqueued.
+    pub enqueued: u64,
+    /// Number of entries dequeued.
+    pub dequeued: u64,
+    /// Number of sends rejected because there was no free slot.
+    pub full: u64,
+    /// Number of receives that found nothing to dequeue.
+    pub empty: u64,
+    /// The maximum number of entries that have been in a single lane.
+    pub peak: u32,
+}
+
+impl QueueStats {
+    pub(super) fn collect<'a, T: 'a>(queues: impl Iterator<Item = Queue<'a, T>>) -> Self {
+        queues.fold(Self::default(), |acc, q| {
+            let (prod, cons) = (&q.off.prod.stats, &q.off.cons.stats);
+            Self {
+                enqueued: acc.enqueued + prod.enqueued.load(Ordering::Relaxed),
+                dequeued: acc.dequeued + cons.dequeued.load(Ordering::Relaxed),
+                full: acc.full + prod.full.load(Ordering::Relaxed),
+                empty: acc.empty + cons.empty.load(Ordering::Relaxed),
+                peak: acc.peak.max(prod.peak.load(Ordering::Relaxed)),
+            }
+        })
+    }
+}
+
+/// A snapshot of traffic counters of a [`Uring`], see
+/// [`Uring::stats`](super::Uring::stats).
+///
+/// [`Uring`]: super::Uring
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct UringStats {
+    /// Counters of the direction where this side sends entries.
+    pub sender: QueueStats,
+    /// Counters of the direction where this side receives entries.
+    pub receiver: QueueStats,
+}
+
+#[cfg(all(test, feature = "alloc", not(loom)))]
+mod tests {
+    use crate::uring::{Builder, Uring, UringMpsc};
+
+    #[test]
+    fn uring_stats() {
+        let mut b = Builder::<i32, i32>::new();
+        b.size_a(4).lane_a(2);
+        let (mut pa, mut pb) = b.build();
+
+        assert_eq!(pa.send_bulk(0..8), 3);
+        assert_eq!(pa.send(3), Err(3));
+        pa.send_to(1, 4).unwrap();
+        assert_eq!(pb.recv_bulk().count(), 3);
+        assert_eq!(pb.recv(), Some(4));
+        assert_eq!(pb.recv(), None);
+        pb.send(5).unwrap();
+        let mut r = pb.reserve(2);
+        r.push(6).unwrap();
+        r.commit();
+
+        let stats = pa.stats();
+        assert_eq!(stats, {
+            let mut s = pb.stats();
+            core::mem::swap(&mut s.sender, &mut s.receiver);
+            s
+        });
+        assert_eq!(stats.sender.enqueued, 4);
+        assert_eq!(stats.sender.dequeued, 4);
+        assert_eq!(stats.sender.full, 1);
+        assert_eq!(stats.sender.empty, 1);
+        assert_eq!(stats.sender.peak, 3);
+        assert_eq!(stats.receiver.enqueued, 2);
+        assert_eq!(stats.receiver.dequeued, 0);
+        assert_eq!(stats.receiver.peak, 2);
+
+        let mut pa = UringMpsc::new(pa);
+        let tx = pa.sender_handle();
+        tx.send(7).unwrap();
+        assert_eq!(pa.recv_guard().map(|g| g.accept()), Some(5));
+        assert_eq!(pb.recv(), Some(7));
+        assert_eq!(pa.stats().sender.enqueued, 5);
+        assert_eq!(pa.stats().receiver.dequeued, 1);
+    }
+}
@@ -0,0 +1,32 @@
+//! Synchronization primitives used by [`Uring`](super::Uring), which are
+//! replaced by those of [`loom`] under `cfg(loom)` to check all possible
+//! interleavings in tests.
+//!
+//! [`loom`]: https://docs.rs/loom
+
+#[cfg(not(loom))]
+pub(crate) use core::hint::spin_loop;
+#[cfg(not(loom))]
+pub(crate) use core::sync::atomic::{AtomicU32, AtomicU64, Ordering, fence};
+
+#[cfg(loom)]
+pub(crate) use loom::hint::spin_loop;
+#[cfg(loom)]
+pub(crate) use loom::sync::atomic::{AtomicU32, AtomicU64, Ordering, fence};
+
+/// Declares a `const fn`, which is not `const` under `cfg(loom)` since atomics
+/// of [`loom`] cannot be created in const contexts.
+///
+/// [`loom`]: https://docs.rs/loom
+macro_rules! const_fn {
+    ($(#[$attr:meta])* $vis:vis fn $($rest:tt)*) => {
+        #[cfg(not(loom))]
+        $(#[$attr])*
+        $vis const fn $($rest)*
+
+        #[cfg(loom)]
+        $(#[$attr])*
+        $vis fn $($rest)*
+    };
+}
+pub(crate) use const_fn;
@@ -0,0 +1,246 @@
+use core::time::Duration;
+
+use super::Uring;
+use super::sync::{self, AtomicU32, Ordering, const_fn};
+
+/// Strategies to wait for a [`Uring`] to become ready.
+pub trait WaitStrategy {
+    /// Whether waiters park on the futex word and have to be woken up by the
+    /// remote side.
+    ///
+    /// If `true`, senders and receivers will wake up parked waiters through
+    /// `FUTEX_WAKE`, which requires the `futex` feature.
+    const PARK: bool = false;
+
+    /// Waits until the futex `word` may no longer equal `expected`, or the
+    /// given `timeout` elapses.
+    ///
+    /// Returning early is always allowed, in which case the waiter checks the
+    /// [`Uring`] and waits again.
+    fn wait(&self, word: &AtomicU32, expected: u32, timeout: Option<Duration>);
+}
+
+/// Busy-waits with [`spin_loop`](core::hint::spin_loop).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Spin;
+
+impl WaitStrategy for Spin {
+    fn wait(&self, _: &AtomicU32, _: u32, _: Option<Duration>) {
+        sync::spin_loop();
+    }
+}
+
+/// Busy-waits with [`yield_now`](std::thread::yield_now).
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Yield;
+
+#[cfg(feature = "std")]
+impl WaitStrategy for Yield {
+    fn wait(&self, _: &AtomicU32, _: u32, _: Option<Duration>) {
+        std::thread::yield_now();
+    }
+}
+
+/// Parks the current thread on the futex word inside [`Header`], which works
+/// across processes as long as the [`Uring`] lives in shared memory.
+///
+/// [`Header`]: super::Header
+#[cfg(all(feature = "futex", target_os = "linux"))]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Futex;
+
+#[cfg(all(feature = "futex", target_os = "linux"))]
+impl WaitStrategy for Futex {
+    const PARK: bool = true;
+
+    fn wait(&self, word: &AtomicU32, expected: u32, timeout: Option<Duration>) {
+        futex::wait(word, expected, timeout);
+    }
+}
+
+/// An event that waiters of a queue can wait for.
+#[repr(C)]
+pub(super) struct Event {
+    /// The futex word, increased every time the event is notified.
+    seq: AtomicU32,
+    /// Number of parked waiters.
+    waiters: AtomicU32,
+    /// See [`NEED_WAKEUP`].
+    flags: AtomicU32,
+}
+
+/// Set while a polling receiver sleeps, so that the remote side rings the
+/// doorbell on its next send, see [`Uring::recv_polling`].
+pub(super) const NEED_WAKEUP: u32 = 1 << 0;
+
+impl Event {
+    const_fn! {
+        pub fn new() -> Self {
+            Self {
+                seq: AtomicU32::new(0),
+                waiters: AtomicU32::new(0),
+                flags: AtomicU32::new(0),
+            }
+        }
+    }
+
+    /// Wakes up all parked waiters.
+    #[inline]
+    pub fn notify(&self) {
+        #[cfg(all(feature = "futex", target_os = "linux"))]
+        if self.has_waiters() {
+            self.seq.fetch_add(1, Ordering::Release);
+            futex::wake(&self.seq);
+        }
+    }
+
+    /// Returns `true` if any waiter is announced by [`Park::announce`].
+    #[cfg_attr(
+        not(any(loom, all(feature = "futex", target_os = "linux"))),
+        allow(dead_code)
+    )]
+    pub(super) fn has_waiters(&self) -> bool {
+        // Pairs with the fence in `Park::announce`, so that either we see the
+        // waiter or the waiter sees our changes.
+        sync::fence(Ordering::SeqCst);
+        self.waiters.load(Ordering::Relaxed) != 0 || self.need_wakeup()
+    }
+
+    pub fn need_wakeup(&self) -> bool {
+        self.flags.load(Ordering::Relaxed) & NEED_WAKEUP != 0
+    }
+}
+
+/// How a waiter announces itself to the remote side before parking.
+#[derive(Clone, Copy)]
+pub(super) enum Park {
+    /// Counted as one of the parked waiters.
+    Waiter,
+    /// Marked with [`NEED_WAKEUP`], which is only used by the single receiver.
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    NeedWakeup,
+}
+
+impl Park {
+    /// Announces the waiter, after which it must check the condition again
+    /// before parking.
+    pub(super) fn announce(self, ev: &Event) {
+        match self {
+            Self::Waiter => _ = ev.waiters.fetch_add(1, Ordering::Relaxed),
+            Self::NeedWakeup => _ = ev.flags.fetch_or(NEED_WAKEUP, Ordering::Relaxed),
+        }
+        // Pairs with the fence in `Event::has_waiters`.
+        sync::fence(Ordering::SeqCst);
+    }
+
+    fn withdraw(self, ev: &Event) {
+        match self {
+            Self::Waiter => _ = ev.waiters.fetch_sub(1, Ordering::Relaxed),
+            Self::NeedWakeup => _ = ev.flags.fetch_and(!NEED_WAKEUP, Ordering::Relaxed),
+        }
+    }
+}
+
+/// Calls `f` until it returns [`Some`], waiting for `event` in between.
+///
+/// It returns [`None`] if `timeout` returns [`Duration::ZERO`], or if the
+/// remote side is disconnected.
+pub(super) fn wait_for<U, T, W>(
+    uring: &mut U,
+    wait: &W,
+    event: fn(&U) -> &Event,
+    f: impl FnMut(&mut U) -> Option<T>,
+    timeout: impl FnMut() -> Option<Duration>,
+) -> Option<T>
+where
+    U: Uring + ?Sized,
+    W: WaitStrategy,
+{
+    park_for(
+        uring,
+        wait,
+        event,
+        |u| u.is_connected(),
+        Park::Waiter,
+        f,
+        timeout,
+    )
+}
+
+/// Like [`wait_for`], but announces the waiter in the given way, and checks
+/// whether `this` is still connected with `connected`.
+pub(super) fn park_for<U, T, W>(
+    this: &mut U,
+    wait: &W,
+    event: fn(&U) -> &Event,
+    connected: fn(&U) -> bool,
+    park: Park,
+    mut f: impl FnMut(&mut U) -> Option<T>,
+    mut timeout: impl FnMut() -> Option<Duration>,
+) -> Option<T>
+where
+    U: ?Sized,
+    W: WaitStrategy,
+{
+    loop {
+        if let Some(t) = f(this) {
+            return Some(t);
+        }
+        let timeout = timeout();
+        if timeout == Some(Duration::ZERO) {
+            return None;
+        }
+
+        let ev = event(this);
+        let seq = ev.seq.load(Ordering::Acquire);
+        if W::PARK {
+            park.announce(ev);
+        }
+        // Check again after we are visible to the remote side.
+        let connected = connected(this);
+        let t = f(this);
+        if t.is_none() && connected {
+            wait.wait(&event(this).seq, seq, timeout);
+        }
+        if W::PARK {
+            park.withdraw(event(this));
+        }
+        if t.is_some() || !connected {
+            return t;
+        }
+    }
+}
+
+#[cfg(all(feature = "futex", target_os = "linux"))]
+mod futex {
+    use core::sync::atomic::AtomicU32;
+    use core::time::Duration;
+
+    // `FUTEX_PRIVATE_FLAG` is not used since the word may be shared between
+    // processes.
+
+    pub fn wait(word: &AtomicU32, expected: u32, timeout: Option<Duration>) {
+        let timeout = timeout.map(|t| libc::timespec {
+            tv_sec: t.as_secs().try_into().unwrap_or(libc::time_t::MAX),
+            tv_nsec: t.subsec_nanos().into(),
+        });
+        unsafe {
+            libc::syscall(
+                libc::SYS_futex,
+                word.as_ptr(),
+                libc::FUTEX_WAIT,
+                expected,
+                timeout
+                    .as_ref()
+                    .map_or(core::ptr::null(), |t| t as *const libc::timespec),
+            );
+        }
+    }
+
+    pub fn wake(word: &AtomicU32) {
+        unsafe {
+            libc::syscall(libc::SYS_futex, word.as_ptr(), libc::FUTEX_WAKE, i32::MAX);
+        }
+    }
+}
@@ -5,8 +5,8 @@ pub mod shm;
 pub use anyhow::{Error, Result};
 use evering::uring;
 
-pub use self::op::{Rqe, RqeData, Sqe, SqeData};
-pub use self::runtime::{Runtime, RuntimeHandle};
+pub use self::op::{Notice, PeerDied, Rqe, RqeData, Sqe, SqeData};
+pub use self::runtime::{HEARTBEAT_INTERVAL, Runtime, RuntimeHandle, Watchdog};
 pub use self::shm::{ShmBox, ShmToken};
 
 pub type ClientUring = uring::UringA<Sqe, Rqe>;
@@ -1,19 +1,33 @@
 #![feature(layout_for_ptr)]
 #![feature(local_waker)]
 
+use std::collections::VecDeque;
 use std::os::fd::{AsFd, FromRawFd, OwnedFd};
 use std::str::FromStr;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result, anyhow};
 use argh::FromArgs;
 use bytesize::ByteSize;
-use evering::uring::Uring;
+use evering::driver::Responder;
+use evering::uring::{
+    BroadcastError, BroadcastReceiver, BroadcastSender, Futex, Uring, UringOverflow,
+};
 use evering_ipc::{
-    ClientUring, Rqe, RqeData, Runtime, RuntimeHandle, ServerUring, ShmBox, ShmHeader, Sqe,
-    SqeData, UringBuilder, op,
+    ClientUring, HEARTBEAT_INTERVAL, Notice, PeerDied, Rqe, RqeData, Runtime, RuntimeHandle,
+    ServerUring, ShmBox, ShmHeader, Sqe, SqeData, UringBuilder, Watchdog, op,
 };
 
+const RECONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long the server keeps polling for requests before it goes to sleep.
+const POLL_IDLE: Duration = Duration::from_millis(1);
+/// Number of updates the client subscribes to.
+const UPDATES: u32 = 4;
+/// Every this many requests, the client gives up waiting after
+/// [`ABANDON_AFTER`], which cancels the request on the server.
+const ABANDON_EVERY: usize = 8;
+const ABANDON_AFTER: Duration = Duration::from_millis(100);
+
 #[derive(Debug, FromArgs)]
 /// IPC based on shared memory
 #[argh(help_triggers("--help"))]
@@ -27,6 +41,9 @@ struct Args {
     /// create the specified shmfile
     #[argh(switch)]
     create: bool,
+    /// attach to an existing shmfile whose previous peer died
+    #[argh(switch)]
+    attach: bool,
     /// type of this app, may be "client" or "server"
     #[argh(option, long = "app")]
     app: AppType,
@@ -118,15 +135,14 @@ pub fn main() -> Result<()> {
 
     // SAFETY: The fd's validity is guaranteed by the parent process.
     let shm = if args.create {
-        let h = UringBuilder::new().build_header();
-        unsafe { ShmHeader::create(shmfd, shmsize, h)?.as_ref() }
+        unsafe { ShmHeader::create(shmfd, shmsize, UringBuilder::new())?.as_ref() }
     } else {
         unsafe { ShmHeader::open(shmfd, shmsize)?.as_ref() }
     };
 
     let disposed = match args.app {
-        AppType::Client => start_client(shm),
-        AppType::Server => start_server(shm),
+        AppType::Client => start_client(shm, args.attach)?,
+        AppType::Server => start_server(shm, args.attach)?,
     };
 
     if disposed {
@@ -136,25 +152,72 @@ pub fn main() -> Result<()> {
     Ok(())
 }
 
-fn start_client(shm: &'static ShmHeader) -> bool {
-    evering_ipc::shm::init_client(shm);
-    let sq = unsafe { ClientUring::from_raw(shm.build_raw_uring()) };
-    tracing::info!("started client, connected={}", sq.is_connected());
+fn start_client(shm: &'static ShmHeader, attach: bool) -> Result<bool> {
+    let sq = if attach {
+        // SAFETY: The previous client is detached by the server, which drops
+        // all entries referring to memory allocated by it.
+        unsafe {
+            let sq = ClientUring::attach(shm.build_raw_uring())
+                .context("failed to attach to the client side")?;
+            evering_ipc::shm::reinit_client(shm);
+            sq
+        }
+    } else {
+        evering_ipc::shm::init_client(shm);
+        unsafe { ClientUring::from_raw(shm.build_raw_uring()) }
+    };
+    tracing::info!(
+        "started client, connected={}, session={}",
+        sq.is_connected(),
+        shm.session()
+    );
+    // SAFETY: The shared memory lives until the end of this program.
+    let mut notices = unsafe { BroadcastReceiver::from_raw(shm.notices()) };
 
     let rt = Runtime::new(sq);
     rt.block_on(async {
+        let updates = RuntimeHandle::spawn(async {
+            let Ok(mut updates) = op::subscribe(UPDATES).await else {
+                tracing::warn!("failed to subscribe, server died");
+                return;
+            };
+            while let Some(update) = updates.next().await {
+                match update {
+                    Ok(seq) => tracing::info!("received update({seq})"),
+                    Err(e) => tracing::warn!("stopped receiving updates, error={e}"),
+                }
+            }
+        });
         let tasks = (0..)
             .map(|i| async move {
                 let ping = fastrand::i32(..);
-                let mut req = ShmBox::new_slice_filled(0, fastrand::usize(8..=32));
-                let resp = ShmBox::new_slice_uninit(fastrand::usize(8..=32));
-                for c in req.iter_mut() {
-                    *c = fastrand::alphanumeric() as u32 as u8;
-                }
+                let req = std::iter::repeat_with(|| fastrand::alphanumeric() as u32 as u8)
+                    .take(fastrand::usize(8..=32))
+                    .collect::<Vec<_>>();
+                let resp_len = fastrand::usize(8..=32);
                 tracing::info!("requested({i}) ping={ping:x}, req={req}", req = bstr(&req));
 
-                let now = std::time::Instant::now();
-                let op::Pong { pong, req: _, resp } = op::ping(fastrand::i32(..), req, resp).await;
+                let now = Instant::now();
+                let abandon = i % ABANDON_EVERY == ABANDON_EVERY - 1;
+                let deadline = RuntimeHandle::now() + ABANDON_AFTER;
+                let res = retry(async || {
+                    let req = ShmBox::new_slice_copied(&req);
+                    let resp = ShmBox::new_slice_uninit(resp_len);
+                    if abandon {
+                        op::ping_until(ping, req, resp, deadline).await
+                    } else {
+                        op::ping(ping, req, resp).await.map(Some)
+                    }
+                })
+                .await;
+                let Ok(res) = res else {
+                    tracing::warn!("failed({i}) server died");
+                    return;
+                };
+                let Some(op::Pong { pong, req: _, resp }) = res else {
+                    tracing::info!("abandoned({i})");
+                    return;
+                };
                 let elapsed = now.elapsed().as_millis();
                 tracing::info!(
                     "responded({i}) pong={pong:x}, resp={resp}, elapsed={elapsed}ms",
@@ -168,28 +231,118 @@ fn start_client(shm: &'static ShmHeader) -> bool {
         for task in tasks {
             task.await;
         }
-        op::exit().await;
-        tracing::info!("exited client");
+        updates.await;
+        match retry(op::exit).await {
+            Ok(()) => tracing::info!("exited client"),
+            Err(e) => tracing::warn!("exited client, error={e}"),
+        }
     });
 
-    rt.into_uring().dispose_raw().is_ok()
+    loop {
+        match notices.try_recv() {
+            Ok(notice) => tracing::info!("received notice, {notice:?}"),
+            Err(BroadcastError::Lagged(n)) => tracing::warn!("missed {n} notices"),
+            Err(BroadcastError::Empty | BroadcastError::Closed) => break,
+        }
+    }
+
+    Ok(rt.into_uring().dispose_raw().is_ok())
 }
 
-fn start_server(shm: &'static ShmHeader) -> bool {
+fn start_server(shm: &'static ShmHeader, attach: bool) -> Result<bool> {
     evering_ipc::shm::init_server(shm);
-    let mut rq = unsafe { ServerUring::from_raw(shm.build_raw_uring()) };
-    tracing::info!("started server, connected={}", rq.is_connected());
+    let rq = if attach {
+        // SAFETY: The previous server is detached by the client.
+        unsafe { ServerUring::attach(shm.build_raw_uring()) }
+            .context("failed to attach to the server side")?
+    } else {
+        unsafe { ServerUring::from_raw(shm.build_raw_uring()) }
+    };
+    // Never lose responses even if the client falls behind.
+    let mut rq = UringOverflow::new(rq);
+    tracing::info!(
+        "started server, connected={}, session={}",
+        rq.is_connected(),
+        shm.session()
+    );
+
+    // SAFETY: The shared memory lives until the end of this program, and the
+    // server is the only sender.
+    let mut notices = unsafe { BroadcastSender::from_raw(shm.notices()) };
+    let mut announced = false;
 
     let mut local_queue = Vec::new();
+    // Requests received but not handled yet, so that cancel entries sent after
+    // them take effect before the server works on them.
+    let mut inbox = VecDeque::new();
+    let mut responder = Responder::new();
+    let mut watchdog = Watchdog::new();
     let mut i = 0;
     loop {
+        if !watchdog.poll(&rq) {
+            if rq.is_connected() {
+                tracing::warn!("client died, waiting for a new one");
+                // SAFETY: The client stopped responding, and a new one must
+                // reattach before accessing the uring.
+                unsafe { rq.get_ref().detach_peer() };
+                // Drop requests from and responses to the dead client.
+                rq.clear_overflow();
+                rq.recv_bulk().for_each(drop);
+                local_queue.clear();
+                inbox.clear();
+                responder = Responder::new();
+                announced = false;
+            }
+            std::thread::sleep(HEARTBEAT_INTERVAL);
+            continue;
+        }
         let mut should_exit = false;
-        if let Some(Sqe { id, data }) = rq.recv() {
+        let sqe = if local_queue.is_empty() && inbox.is_empty() {
+            // Poll for a while after the last request, then park until the
+            // client rings the doorbell, but wake up in time to send
+            // heartbeats.
+            match rq.recv_polling(&Futex, POLL_IDLE, HEARTBEAT_INTERVAL) {
+                Some(sqe) => Some(sqe),
+                None if rq.is_connected() => continue,
+                None => break,
+            }
+        } else {
+            rq.recv()
+        };
+        for sqe in sqe.into_iter().chain(rq.recv_bulk()) {
+            match sqe.data {
+                SqeData::Cancel => {
+                    if responder.cancel(sqe.id) {
+                        tracing::info!("cancelled request, id={:?}", sqe.id);
+                    }
+                },
+                _ => {
+                    responder.accept(sqe.id);
+                    inbox.push_back(sqe);
+                },
+            }
+        }
+        if let Some(Sqe { id, data }) = inbox.pop_front() {
+            if !announced {
+                // Tell the client about the configuration once it starts
+                // submitting, by when it must have subscribed.
+                notices.send(Notice::Config {
+                    heartbeat_interval_ms: HEARTBEAT_INTERVAL.as_millis() as u32,
+                });
+                announced = true;
+            }
             let data = match data {
                 SqeData::Exit => {
                     should_exit = true;
+                    // Published before the exit response, so that the client
+                    // finds it once it exits.
+                    notices.send(Notice::Shutdown);
                     RqeData::Exited
                 },
+                SqeData::Ping { .. } if responder.is_cancelled(id) => {
+                    tracing::info!("skipped({i}) cancelled request");
+                    RqeData::Cancelled
+                },
                 SqeData::Ping { ping, req, resp } => {
                     let delay = (ping as u64 % 450) + 50;
                     unsafe {
@@ -207,29 +360,60 @@ fn start_server(shm: &'static ShmHeader) -> bool {
                         pong: fastrand::i32(..),
                     }
                 },
+                SqeData::Subscribe { count } => {
+                    tracing::info!("accepted({i}) subscription, count={count}");
+                    // Push all updates but the last one immediately, which
+                    // is replied along with other responses.
+                    let last = count.saturating_sub(1);
+                    for seq in 0..last {
+                        _ = rq.send(Rqe {
+                            id,
+                            data: RqeData::Update { seq, more: true },
+                        });
+                    }
+                    RqeData::Update {
+                        seq: last,
+                        more: false,
+                    }
+                },
+                SqeData::Cancel => unreachable!(),
             };
             i += 1;
             local_queue.push(Rqe { id, data });
         }
 
-        if local_queue.is_empty() {
-            std::thread::yield_now();
-        } else if should_exit || fastrand::bool() {
+        if !local_queue.is_empty() && (should_exit || fastrand::bool()) {
             // Randomize the returned response
             fastrand::shuffle(&mut local_queue);
             for rqe in local_queue.drain(..) {
+                responder.finish(rqe.id);
                 tracing::info!("replied response, data={:x?}", rqe.data);
-                rq.send(rqe).expect("out of capacity");
+                _ = rq.send(rqe);
             }
         }
 
         if should_exit {
-            tracing::info!("exited server");
+            tracing::info!("exited server, stats={:?}", rq.stats());
             break;
         }
     }
 
-    rq.dispose_raw().is_ok()
+    drop(notices);
+    // Make sure the exit response is delivered.
+    rq.flush_blocking(&Futex);
+    Ok(rq.into_inner().0.dispose_raw().is_ok())
+}
+
+/// Retries `f` until the server reattaches, or gives up after
+/// [`RECONNECT_TIMEOUT`].
+async fn retry<T>(mut f: impl AsyncFnMut() -> Result<T, PeerDied>) -> Result<T, PeerDied> {
+    let deadline = Instant::now() + RECONNECT_TIMEOUT;
+    loop {
+        match f().await {
+            Err(_) if Instant::now() < deadline => local_executor::yield_now().await,
+            r => return r,
+        }
+    }
 }
 
 fn bstr(bytes: &[u8]) -> &str {
@@ -1,24 +1,30 @@
+use std::fmt;
 use std::mem::MaybeUninit;
+use std::time::Duration;
 
 use evering::driver::OpId;
-use evering::op::{Cancellation, Completable};
+use evering::op::{Cancellation, Completable, Expirable, Multishot, OpStream};
+use evering::uring::ShmSafe;
 
 use crate::runtime::RuntimeHandle;
 use crate::shm::{ShmBox, ShmToken};
 
-#[derive(Debug)]
+#[derive(Debug, ShmSafe)]
+#[repr(C)]
 pub struct Sqe {
     pub id: OpId,
     pub data: SqeData,
 }
 
-#[derive(Debug)]
+#[derive(Debug, ShmSafe)]
+#[repr(C)]
 pub struct Rqe {
     pub id: OpId,
     pub data: RqeData,
 }
 
-#[derive(Debug)]
+#[derive(Debug, ShmSafe)]
+#[repr(u32)]
 pub enum SqeData {
     Exit,
     Ping {
@@ -26,14 +32,57 @@ pub enum SqeData {
         req: ShmToken<[u8]>,
         resp: ShmToken<[MaybeUninit<u8>]>,
     },
+    /// Subscribes to `count` updates, see [`subscribe`].
+    Subscribe {
+        count: u32,
+    },
+    /// Cancels the request of the same id, which is still responded so that
+    /// its resources can be recycled.
+    Cancel,
 }
 
-#[derive(Debug)]
+#[derive(Debug, ShmSafe)]
+#[repr(u32)]
 pub enum RqeData {
     Exited,
-    Pong { pong: i32 },
+    Pong {
+        pong: i32,
+    },
+    /// An update of a subscription, which is followed by more updates unless
+    /// `more` is `false`.
+    Update {
+        seq: u32,
+        more: bool,
+    },
+    /// The request was cancelled before the server started working on it.
+    Cancelled,
+    /// Never sent by the server, but used to fail pending operations locally
+    /// once the server is dead.
+    PeerDied,
 }
 
+/// A notification published by the server to every attached client.
+#[derive(Clone, Copy, Debug, ShmSafe)]
+#[repr(u32)]
+pub enum Notice {
+    /// The current configuration, published when the server starts.
+    Config { heartbeat_interval_ms: u32 },
+    /// The server is shutting down.
+    Shutdown,
+}
+
+/// An error returned if the remote side died before an operation completed.
+#[derive(Debug)]
+pub struct PeerDied;
+
+impl fmt::Display for PeerDied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("peer died")
+    }
+}
+
+impl std::error::Error for PeerDied {}
+
 struct Ping {
     req: ShmBox<[u8]>,
     resp: ShmBox<[MaybeUninit<u8>]>,
@@ -44,53 +93,122 @@ pub struct Pong {
     pub resp: ShmBox<[u8]>,
 }
 unsafe impl Completable for Ping {
-    type Output = Pong;
+    type Output = Result<Pong, PeerDied>;
     type Driver = RuntimeHandle;
     fn complete(self, _drv: &RuntimeHandle, payload: RqeData) -> Self::Output {
-        let RqeData::Pong { pong } = payload else {
-            unreachable!()
+        let pong = match payload {
+            RqeData::Pong { pong } => pong,
+            RqeData::PeerDied => return Err(PeerDied),
+            _ => unreachable!(),
         };
-        Pong {
+        Ok(Pong {
             pong,
             req: self.req,
             resp: unsafe { self.resp.assume_init() },
-        }
+        })
     }
     fn cancel(self, _drv: &RuntimeHandle) -> Cancellation {
-        Cancellation::recycle((self.req, self.resp))
+        // Tell the server to skip it, but keep the buffers until it responds.
+        Cancellation::recycle((self.req, self.resp)).remote()
     }
 }
 
-pub async fn ping(ping: i32, req: ShmBox<[u8]>, resp: ShmBox<[MaybeUninit<u8>]>) -> Pong {
-    RuntimeHandle::submit(Ping { req, resp }, |id, p| Sqe {
+pub async fn ping(
+    ping: i32,
+    req: ShmBox<[u8]>,
+    resp: ShmBox<[MaybeUninit<u8>]>,
+) -> Result<Pong, PeerDied> {
+    RuntimeHandle::submit(Ping { req, resp }, |id, p| ping_entry(id, ping, p)).await
+}
+
+/// Same as [`ping`], but gives up with [`None`] once `deadline` elapses, see
+/// [`RuntimeHandle::now`]. The request is then cancelled on the server.
+pub async fn ping_until(
+    ping: i32,
+    req: ShmBox<[u8]>,
+    resp: ShmBox<[MaybeUninit<u8>]>,
+    deadline: Duration,
+) -> Result<Option<Pong>, PeerDied> {
+    let data = Timeout(Ping { req, resp });
+    RuntimeHandle::submit_until(data, deadline, |id, p| ping_entry(id, ping, &p.0)).await
+}
+
+fn ping_entry(id: OpId, ping: i32, p: &Ping) -> Sqe {
+    Sqe {
         id,
         data: SqeData::Ping {
             ping,
             req: ShmBox::as_shm(&p.req),
             resp: ShmBox::as_shm(&p.resp),
         },
-    })
-    .await
+    }
+}
+
+/// Resolves to [`None`] if the inner operation is not completed before its
+/// deadline.
+struct Timeout<T>(T);
+unsafe impl<T: Completable<Driver = RuntimeHandle>> Completable for Timeout<T> {
+    type Output = Option<T::Output>;
+    type Driver = RuntimeHandle;
+    fn complete(self, drv: &RuntimeHandle, payload: RqeData) -> Self::Output {
+        Some(self.0.complete(drv, payload))
+    }
+    fn cancel(self, drv: &RuntimeHandle) -> Cancellation {
+        self.0.cancel(drv)
+    }
+}
+impl<T: Completable<Driver = RuntimeHandle>> Expirable for Timeout<T> {
+    fn expired(_drv: &RuntimeHandle) -> Self::Output {
+        None
+    }
 }
 
 struct Exit;
 unsafe impl Completable for Exit {
-    type Output = ();
+    type Output = Result<(), PeerDied>;
     type Driver = RuntimeHandle;
     fn complete(self, _drv: &RuntimeHandle, payload: RqeData) -> Self::Output {
-        let RqeData::Exited = payload else {
-            unreachable!()
-        };
+        match payload {
+            RqeData::Exited => Ok(()),
+            RqeData::PeerDied => Err(PeerDied),
+            _ => unreachable!(),
+        }
     }
     fn cancel(self, _drv: &RuntimeHandle) -> Cancellation {
         Cancellation::noop()
     }
 }
 
-pub async fn exit() {
+pub async fn exit() -> Result<(), PeerDied> {
     RuntimeHandle::submit(Exit, |id, _| Sqe {
         id,
         data: SqeData::Exit,
     })
     .await
 }
+
+pub struct Subscribe;
+unsafe impl Multishot for Subscribe {
+    type Item = Result<u32, PeerDied>;
+    type Driver = RuntimeHandle;
+    fn next(&mut self, _drv: &RuntimeHandle, payload: RqeData) -> Self::Item {
+        match payload {
+            RqeData::Update { seq, .. } => Ok(seq),
+            RqeData::PeerDied => Err(PeerDied),
+            _ => unreachable!(),
+        }
+    }
+    fn cancel(self, _drv: &RuntimeHandle) -> Cancellation {
+        Cancellation::noop()
+    }
+}
+
+/// Subscribes to `count` updates pushed by the server, which ends after the
+/// last update or once the server dies.
+pub async fn subscribe(count: u32) -> Result<OpStream<Subscribe>, PeerDied> {
+    RuntimeHandle::submit_multishot(Subscribe, |id, _| Sqe {
+        id,
+        data: SqeData::Subscribe { count },
+    })
+    .await
+}
@@ -1,22 +1,36 @@
 use std::cell::RefCell;
 use std::mem::ManuallyDrop;
+use std::pin::pin;
 use std::rc::{Rc, Weak};
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
 
 use evering::driver::OpId;
-use evering::op::Completable;
+use evering::op::{Completable, Expirable, Multishot, OpStream};
+use evering::uring::{PeerMonitor, Uring};
 use evering_utils::runtime::ExecutorRef;
 use local_executor::Task;
 
-use crate::op::{Rqe, RqeData, Sqe};
+use crate::op::{PeerDied, Rqe, RqeData, Sqe, SqeData};
+
+/// Interval between two heartbeats.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_millis(100);
+/// The remote side is considered dead after missing this many heartbeats.
+pub const MAX_MISSED_HEARTBEATS: u32 = 20;
+/// Maximum time to wait for in-flight operations when dropping a runtime.
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(1);
 
 type Sender = evering::uring::Sender<Sqe, Rqe>;
 type RuntimeInner = evering_utils::runtime::Runtime<RqeData, Sender>;
 
-pub struct Runtime(ManuallyDrop<Rc<RuntimeInner>>);
+pub struct Runtime(ManuallyDrop<Rc<RuntimeInner>>, RefCell<Watchdog>);
 
 impl Runtime {
     pub fn new(sender: Sender) -> Self {
-        Self(ManuallyDrop::new(Rc::new(RuntimeInner::new(sender))))
+        Self(
+            ManuallyDrop::new(Rc::new(RuntimeInner::new(sender))),
+            RefCell::new(Watchdog::new()),
+        )
     }
 
     pub fn block_on<T>(&self, fut: impl Future<Output = T>) -> T {
@@ -30,9 +44,58 @@ impl Runtime {
     }
 
     async fn run_on_no_guard<T>(&self, fut: impl Future<Output = T>) -> T {
-        self.0
-            .run_on(|rqe| _ = self.0.driver.complete(rqe.id, rqe.data), fut)
-            .await
+        let mut fut = pin!(fut);
+        let fut = std::future::poll_fn(|cx| {
+            self.watch();
+            self.0.driver.expire(RuntimeHandle::now());
+            let res = fut.as_mut().poll(cx);
+            // Operations dropped by the last poll are cancelled on the server.
+            self.0.send_cancels(|id| Sqe {
+                id,
+                data: SqeData::Cancel,
+            });
+            res
+        });
+        let complete = |rqe: Rqe| {
+            let res = match rqe.data {
+                data @ RqeData::Update { more: true, .. } => {
+                    self.0.driver.complete_more(rqe.id, data)
+                },
+                data => self.0.driver.complete(rqe.id, data),
+            };
+            // Responses of cancelled operations are expected, but others
+            // indicate a misbehaving server.
+            match res {
+                Err(e) if !e.is_cancelled() => {
+                    tracing::warn!("dropped response, id={:?}, error={e}", rqe.id)
+                },
+                _ => {},
+            }
+        };
+        self.0.run_on(complete, fut).await
+    }
+
+    fn watch(&self) {
+        let mut watchdog = self.1.borrow_mut();
+        let mut uring = self.0.uring.borrow_mut();
+        let died = watchdog.died();
+        if watchdog.poll(&*uring) {
+            if died {
+                tracing::info!("server reattached, session={}", uring.session());
+            }
+            return;
+        }
+        if !died {
+            tracing::warn!("server died, failed pending operations");
+            // SAFETY: The server stopped responding, and a new one must
+            // reattach before accessing the uring.
+            unsafe { uring.detach_peer() };
+            // Responses sent by the dead server are no longer expected.
+            uring.recv_bulk().for_each(drop);
+        }
+        drop(uring);
+        // Operations submitted after the server died are never completed.
+        self.0.fail_pending(|_| RqeData::PeerDied);
     }
 
     pub fn into_uring(mut self) -> Sender {
@@ -44,17 +107,62 @@ impl Runtime {
     }
 }
 
+impl Runtime {
+    /// Waits for in-flight operations to complete, and reclaims resources of
+    /// cancelled ones which the server never responded to.
+    fn shutdown(&self) {
+        let deadline = RuntimeHandle::now() + SHUTDOWN_TIMEOUT;
+        if self
+            .block_on(self.0.driver.shutdown(Some(deadline)))
+            .is_ok()
+        {
+            return;
+        }
+        // A connected server may still write to the resources.
+        let connected = self.0.uring.borrow().is_connected();
+        for (id, cancellation) in self.0.driver.force_close() {
+            tracing::warn!("reclaimed orphaned operation, id={id:?}, leaked={connected}");
+            if connected {
+                std::mem::forget(cancellation);
+            }
+        }
+    }
+}
+
 impl Drop for Runtime {
     fn drop(&mut self) {
-        let rc = unsafe { ManuallyDrop::take(&mut self.0) };
-        // Leak the Driver so that no pending resources will expire.
-        // TODO: should wait instead?
-        if !rc.driver.is_empty() {
-            std::mem::forget(rc);
+        if std::thread::panicking() {
+            let rc = unsafe { ManuallyDrop::take(&mut self.0) };
+            // Avoid panicking again in `Driver::drop` while unwinding.
+            if !rc.driver.is_empty() {
+                std::mem::forget(rc);
+            }
+            return;
+        }
+        if !self.0.driver.is_empty() {
+            self.shutdown();
+        }
+        if !self.0.driver.is_empty() {
+            if self.0.uring.borrow().is_connected() {
+                // A connected server may still write to resources of in-flight
+                // operations, which are leaked along with their tasks.
+                tracing::warn!("leaked in-flight operations of an unresponsive server");
+                self.0.uring.borrow().close();
+                std::mem::forget(unsafe { ManuallyDrop::take(&mut self.0) });
+                return;
+            }
+            // Tasks still awaiting operations are failed and polled once, so
+            // that they drop their operations before the driver.
+            self.0.fail_pending(|_| RqeData::PeerDied);
+            self.block_on(local_executor::yield_now());
         }
+        unsafe { ManuallyDrop::drop(&mut self.0) }
     }
 }
 
+/// The epoch of operation deadlines, see [`RuntimeHandle::now`].
+static EPOCH: LazyLock<Instant> = LazyLock::new(Instant::now);
+
 thread_local! {
     static CX: RefCell<Weak<RuntimeInner>> = const { RefCell::new(Weak::new()) };
 }
@@ -79,6 +187,7 @@ impl local_executor::ExecutorHandle for RuntimeHandle {
 impl evering::driver::DriverHandle for RuntimeHandle {
     type Payload = RqeData;
     type Ext = ();
+    type Cancellation = evering::op::Cancellation;
     type Ref = evering_utils::runtime::DriverRef<RuntimeHandle>;
     fn get(&self) -> Self::Ref {
         evering_utils::runtime::DriverRef::new(self)
@@ -110,10 +219,107 @@ impl RuntimeHandle {
         RuntimeInner::spawn(Self, fut)
     }
 
-    pub async fn submit<T>(data: T, new_entry: impl FnOnce(OpId, &mut T) -> Sqe) -> T::Output
+    /// Submits an operation, failing immediately if the connection is closed.
+    pub async fn submit<T, U>(
+        data: T,
+        new_entry: impl FnOnce(OpId, &mut T) -> Sqe,
+    ) -> Result<U, PeerDied>
     where
-        T: Completable<Driver = RuntimeHandle>,
+        T: Completable<Driver = RuntimeHandle, Output = Result<U, PeerDied>>,
     {
+        let rt = evering_utils::runtime::RuntimeHandle::get(&Self);
+        if !rt.uring.borrow().is_connected() {
+            return Err(PeerDied);
+        }
         RuntimeInner::submit(Self, data, new_entry).await.await
     }
+
+    /// Submits an operation which resolves to [`None`] once `deadline` elapses,
+    /// failing immediately if the connection is closed.
+    pub async fn submit_until<T, U>(
+        data: T,
+        deadline: Duration,
+        new_entry: impl FnOnce(OpId, &mut T) -> Sqe,
+    ) -> Result<Option<U>, PeerDied>
+    where
+        T: Expirable<Driver = RuntimeHandle, Output = Option<Result<U, PeerDied>>>,
+    {
+        let rt = evering_utils::runtime::RuntimeHandle::get(&Self);
+        if !rt.uring.borrow().is_connected() {
+            return Err(PeerDied);
+        }
+        let op = RuntimeInner::submit_with_deadline(Self, deadline, data, new_entry).await;
+        op.await.transpose()
+    }
+
+    /// Returns the current time for operation deadlines, which are expired
+    /// whenever the runtime is polled.
+    pub fn now() -> Duration {
+        EPOCH.elapsed()
+    }
+
+    /// Submits a multishot operation, failing immediately if the connection is
+    /// closed.
+    pub async fn submit_multishot<T, U>(
+        data: T,
+        new_entry: impl FnOnce(OpId, &mut T) -> Sqe,
+    ) -> Result<OpStream<T>, PeerDied>
+    where
+        T: Multishot<Driver = RuntimeHandle, Item = Result<U, PeerDied>>,
+    {
+        let rt = evering_utils::runtime::RuntimeHandle::get(&Self);
+        if !rt.uring.borrow().is_connected() {
+            return Err(PeerDied);
+        }
+        Ok(RuntimeInner::submit_multishot(Self, data, new_entry).await)
+    }
+}
+
+/// Sends heartbeats and checks the remote side periodically.
+pub struct Watchdog {
+    monitor: PeerMonitor,
+    last_check: Instant,
+    session: Option<u32>,
+    died: bool,
+}
+
+impl Watchdog {
+    pub fn new() -> Self {
+        Self {
+            monitor: PeerMonitor::new(MAX_MISSED_HEARTBEATS),
+            last_check: Instant::now(),
+            session: None,
+            died: false,
+        }
+    }
+
+    /// Sends a heartbeat and checks the remote side if at least
+    /// [`HEARTBEAT_INTERVAL`] has elapsed since the last check.
+    ///
+    /// It returns `false` once the remote side stops sending heartbeats, until
+    /// a new remote side attaches. A gracefully closed connection is not
+    /// considered dead.
+    pub fn poll<U: Uring>(&mut self, uring: &U) -> bool {
+        let session = uring.session();
+        if self.session.replace(session).is_some_and(|s| s != session) {
+            self.monitor = PeerMonitor::new(MAX_MISSED_HEARTBEATS);
+            self.died = false;
+        }
+        if !self.died && self.last_check.elapsed() >= HEARTBEAT_INTERVAL {
+            self.last_check = Instant::now();
+            uring.heartbeat();
+            self.died = uring.is_connected() && !self.monitor.check(uring);
+        }
+        !self.died
+    }
+
+    pub fn died(&self) -> bool {
+        self.died
+    }
+}
+
+impl Default for Watchdog {
+    fn default() -> Self {
+        Self::new()
+    }
 }
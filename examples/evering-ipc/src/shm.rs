@@ -10,43 +10,62 @@ use std::os::fd::BorrowedFd;
 use std::ptr::NonNull;
 use std::sync::atomic::{AtomicBool, Ordering};
 
-use anyhow::Context;
-use evering::uring::{Header as UringHeader, RawUring};
+use anyhow::{Context, ensure};
+use evering::uring::{
+    BroadcastHeader, Builder as UringBuilder, Header as UringHeader, RawBroadcast, RawUring,
+    ShmSafe, TypeLayout,
+};
 use rlsf::Tlsf;
 
-pub use self::boxed::{ShmBox, init_client, init_server};
+pub use self::boxed::{ShmBox, init_client, init_server, reinit_client};
 use crate::Result;
+use crate::op::Notice;
+
+/// Number of slots of the notice broadcast ring.
+const NOTICES: usize = 16;
 
 /// [`ShmHeader`] contains necessary metadata of a shared memory region.
 ///
 /// Memory layout of the entire shared memory is illustrated as below,
 ///
 /// ```svgbob
-/// .-----------------------------------------------------------------------------.
-/// |                   |               |                   |                     |
-/// | [1] uring offsets | [2] allocator | [3] uring buffers | [4] free memory ... |
-/// | ^                 |               |                   |                   ^ |
-/// '-|-------------------------------------------------------------------------|-'
-///   '-- start of the shared memory (page aligned)                             |
-///                                                  end of the shared memory --'
+/// .-------------------------------------------------------------------------------.
+/// |                            |                          |                     |
+/// | [1] offsets and allocator  | [2] uring and notices    | [3] free memory ... |
+/// | ^                          |                          |                   ^ |
+/// '-|---------------------------------------------------------------------------|-'
+///   '-- start of the shared memory (page aligned)                               |
+///                                                    end of the shared memory --'
 /// ```
 ///
-/// 1. Uring offsets are used to build [`RawUring`].
-/// 2. Each shared memory region comes with one single-thread allocator.
-///    Typically, it will be taken by the client after initialization.
-/// 3. The submitted requests and responses are stored in these buffers.
-/// 4. The rest of the shared memory are managed by the allocator. [`ShmBox`]
+/// 1. Offsets of the uring are used to build [`RawUring`]. Each shared memory
+///    region comes with one single-thread allocator. Typically, it will be
+///    taken by the client after initialization.
+/// 2. The uring is built by [`UringBuilder::build_in`], where the submitted
+///    requests and responses are stored. It is followed by a broadcast ring
+///    of [`Notice`]s, which the server publishes to every attached client.
+/// 3. The rest of the shared memory are managed by the allocator. [`ShmBox`]
 ///    provides similar APIS to [`Box`], but it is allocated and deallocated by
 ///    the shared memory [`Allocator`] instead of the global allocator.
+///
+/// The layout of [`ShmHeader`] is placed at the start so that it can be
+/// validated in [`ShmHeader::open`] before anything else is read, followed by
+/// the format of the uring header.
+#[repr(C)]
 pub struct ShmHeader<A = crate::op::Sqe, B = crate::op::Rqe, Ext = ()> {
-    header: UringHeader<Ext>,
-    // Relative offsets of uring buffers
+    // Layout of this header, which also covers the allocator
+    layout: TypeLayout,
+    // Relative offsets of the uring header and buffers
+    header: usize,
     buf_a: usize,
     buf_b: usize,
+    // Relative offsets of the notice broadcast header and slots
+    notices_header: usize,
+    notices_buf: usize,
     allocator_taken: AtomicBool,
     allocator: Allocator, // Max block size: 32 << 24 = 512MB
     free_memory: (usize, usize),
-    marker: PhantomData<(A, B)>,
+    marker: PhantomData<(A, B, Ext)>,
 }
 
 impl<A, B, Ext> ShmHeader<A, B, Ext> {
@@ -57,35 +76,39 @@ impl<A, B, Ext> ShmHeader<A, B, Ext> {
     pub unsafe fn create(
         fd: BorrowedFd,
         size: usize,
-        header: UringHeader<Ext>,
-    ) -> Result<NonNull<Self>> {
+        builder: UringBuilder<A, B, Ext>,
+    ) -> Result<NonNull<Self>>
+    where
+        A: ShmSafe,
+        B: ShmSafe,
+    {
         // Calculate offsets
-        let mut cur = size_of::<Self>();
-
-        let layout_a = Layout::array::<A>(header.size_a()).unwrap();
-        let buf_a = align_up(cur, layout_a.align());
-        cur = buf_a + layout_a.size();
-
-        let layout_b = Layout::array::<B>(header.size_b()).unwrap();
-        let buf_b = align_up(cur, layout_b.align());
-        cur = buf_b + layout_b.size();
-
-        assert!(cur < size, "capacity of shared memory is too small");
-
-        // Initialize shared memory and the uring buffers
+        let uring = builder.layout();
+        let (layout, start) = Layout::new::<Self>().extend(uring.layout).unwrap();
+        let notices = RawBroadcast::<Notice>::layout(NOTICES);
+        let (layout, notices_start) = layout.extend(notices.layout).unwrap();
+        let free = layout.size();
+        assert!(free < size, "capacity of shared memory is too small");
+
+        // Initialize shared memory and the uring
         unsafe {
             shm_grow(fd, size)?;
             let this = shm_mmap(fd, size, 0)?.cast::<Self>();
 
             this.write(Self {
-                header,
-                buf_a,
-                buf_b,
+                layout: TypeLayout::of::<Self>(),
+                header: start + uring.header,
+                buf_a: start + uring.buf_a,
+                buf_b: start + uring.buf_b,
+                notices_header: notices_start + notices.header,
+                notices_buf: notices_start + notices.buf,
                 allocator_taken: AtomicBool::new(false),
                 allocator: Allocator::new(),
-                free_memory: (cur, size),
+                free_memory: (free, size),
                 marker: PhantomData,
             });
+            builder.build_in(this.cast::<u8>().byte_add(start));
+            RawBroadcast::<Notice>::build_in(NOTICES, this.cast::<u8>().byte_add(notices_start));
 
             Ok(this)
         }
@@ -95,14 +118,71 @@ impl<A, B, Ext> ShmHeader<A, B, Ext> {
     ///
     /// The given `fd` must be valid for the remaining lifetime of the running
     /// program.
-    pub unsafe fn open(fd: BorrowedFd, size: usize) -> Result<NonNull<Self>> {
-        assert_eq!(
-            nix::sys::stat::fstat(fd)
-                .context("failed to read shmfd")?
-                .st_size as i64,
-            size as i64
+    pub unsafe fn open(fd: BorrowedFd, size: usize) -> Result<NonNull<Self>>
+    where
+        A: ShmSafe,
+        B: ShmSafe,
+    {
+        let found = nix::sys::stat::fstat(fd)
+            .context("failed to read shmfd")?
+            .st_size;
+        ensure!(
+            found == size as i64,
+            "mismatched size of shared memory, expected {size}, found {found}"
         );
-        unsafe { shm_mmap(fd, size, 0).map(NonNull::cast) }
+        ensure!(
+            size >= size_of::<Self>(),
+            "shared memory is too small to contain a header"
+        );
+        unsafe {
+            let this = shm_mmap(fd, size, 0)?.cast::<Self>();
+            if let Err(e) = this.as_ref().check_layout(size) {
+                _ = Self::close(this, size);
+                return Err(e);
+            }
+            Ok(this)
+        }
+    }
+
+    fn check_layout(&self, size: usize) -> Result<()> {
+        // The layout must be checked first, since it determines where the rest
+        // fields are placed.
+        let expected = TypeLayout::of::<Self>();
+        ensure!(
+            self.layout == expected,
+            "mismatched layout of shared memory header, expected {expected:?}, found {:?}",
+            self.layout
+        );
+        ensure!(
+            self.free_memory.1 == size,
+            "mismatched size of shared memory, expected {size}, created with {}",
+            self.free_memory.1
+        );
+        let header = Layout::new::<UringHeader<Ext>>();
+        ensure!(
+            self.in_bounds(self.header, header),
+            "uring header out of bounds, offset={}",
+            self.header
+        );
+        self.uring_header()
+            .check_layout::<A, B>()
+            .context("incompatible uring header")?;
+        ensure!(
+            self.in_bounds(self.notices_header, Layout::new::<BroadcastHeader>()),
+            "notice header out of bounds, offset={}",
+            self.notices_header
+        );
+        self.notices_header()
+            .check_layout::<Notice>()
+            .context("incompatible notice header")?;
+        Ok(())
+    }
+
+    fn in_bounds(&self, offset: usize, layout: Layout) -> bool {
+        offset % layout.align() == 0
+            && offset
+                .checked_add(layout.size())
+                .is_some_and(|end| end <= self.free_memory.0)
     }
 
     /// # Safety
@@ -118,17 +198,66 @@ impl<A, B, Ext> ShmHeader<A, B, Ext> {
         let mut raw = RawUring::<A, B, Ext>::dangling();
         unsafe {
             let start = self.start_ptr();
-            raw.header = NonNull::from(&self.header);
+            raw.header = start.byte_add(self.header).cast();
             raw.buf_a = start.byte_add(self.buf_a).cast();
             raw.buf_b = start.byte_add(self.buf_b).cast();
         }
         raw
     }
 
+    /// Returns the broadcast ring of [`Notice`]s, from which the server
+    /// creates the only sender and each client subscribes a receiver.
+    pub fn notices(&self) -> RawBroadcast<Notice> {
+        let mut raw = RawBroadcast::dangling();
+        unsafe {
+            let start = self.start_ptr();
+            raw.header = start.byte_add(self.notices_header).cast();
+            raw.buf = start.byte_add(self.notices_buf).cast();
+        }
+        raw
+    }
+
+    /// Returns the session epoch of the uring, which is increased every time
+    /// a restarted peer attaches.
+    pub fn session(&self) -> u32 {
+        self.uring_header().session()
+    }
+
+    fn uring_header(&self) -> &UringHeader<Ext> {
+        unsafe { self.start_ptr().byte_add(self.header).cast().as_ref() }
+    }
+
+    fn notices_header(&self) -> &BroadcastHeader {
+        unsafe {
+            self.start_ptr()
+                .byte_add(self.notices_header)
+                .cast()
+                .as_ref()
+        }
+    }
+
+    /// Takes the allocator again after the previous client is gone.
+    ///
+    /// The allocator is reset since it contains addresses of the previous
+    /// client, so all memory allocated by the previous client is released.
+    ///
+    /// # Safety
+    ///
+    /// Memory allocated by the previous client must never be accessed again.
+    pub unsafe fn reclaim_allocator(&self) -> &Allocator {
+        self.allocator_taken.store(true, Ordering::Relaxed);
+        *self.allocator.tlsf.borrow_mut() = Tlsf::new();
+        self.init_allocator()
+    }
+
     pub fn get_allocator(&self) -> &Allocator {
         if self.allocator_taken.swap(true, Ordering::Acquire) {
             panic!("allocator has been taken");
         }
+        self.init_allocator()
+    }
+
+    fn init_allocator(&self) -> &Allocator {
         unsafe {
             let (data_start, data_end) = self.free_memory;
             let data = self.start_ptr().byte_add(data_start);
@@ -146,20 +275,24 @@ impl<A, B, Ext> ShmHeader<A, B, Ext> {
         &self.allocator
     }
 
-    pub fn get_shm<T: ?Sized>(&self, ptr: NonNull<T>) -> ShmToken<T> {
+    pub fn get_shm<T: ?Sized + ShmPointee>(&self, ptr: NonNull<T>) -> ShmToken<T> {
         let start = self.start_addr().get();
         let addr = ptr.addr().get();
         assert!(addr > start);
-        let shm = NonZeroUsize::new(addr - start).unwrap();
-        ShmToken(ptr.with_addr(shm))
+        let offset = NonZero::new((addr - start) as u64).unwrap();
+        ShmToken {
+            offset,
+            len: T::len(ptr) as u64,
+            marker: PhantomData,
+        }
     }
 
     /// # Safety
     ///
     /// The given `shm` must belong to this memory region.
-    pub fn get_ptr<T: ?Sized>(&self, shm: ShmToken<T>) -> NonNull<T> {
-        let start = self.start_addr().get();
-        unsafe { shm.0.byte_add(start) }
+    pub fn get_ptr<T: ?Sized + ShmPointee>(&self, shm: ShmToken<T>) -> NonNull<T> {
+        let data = unsafe { self.start_ptr().byte_add(shm.offset.get() as usize) };
+        T::from_raw_parts(data, shm.len as usize)
     }
 
     fn start_addr(&self) -> NonZeroUsize {
@@ -240,9 +373,19 @@ impl Allocator {
     }
 }
 
-pub struct ShmToken<T: ?Sized>(NonNull<T>);
+/// A pointer relative to the start of the shared memory.
+///
+/// The offset and the length of slices are stored as `u64`s, so that the
+/// token has the same layout in both processes.
+#[derive(ShmSafe)]
+#[repr(C)]
+pub struct ShmToken<T: ?Sized> {
+    offset: NonZero<u64>,
+    len: u64,
+    marker: PhantomData<T>,
+}
 
-impl<T: ?Sized> ShmToken<T> {
+impl<T: ?Sized + ShmPointee> ShmToken<T> {
     pub fn as_ptr(&self) -> NonNull<T> {
         boxed::ShmHandle::get().get_ptr(*self)
     }
@@ -250,8 +393,9 @@ impl<T: ?Sized> ShmToken<T> {
 
 impl<T: ?Sized> fmt::Debug for ShmToken<T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_tuple("ShmToken")
-            .field(&self.0.cast::<()>())
+        f.debug_struct("ShmToken")
+            .field("offset", &self.offset)
+            .field("len", &self.len)
             .finish()
     }
 }
@@ -263,6 +407,32 @@ impl<T: ?Sized> Clone for ShmToken<T> {
 }
 impl<T: ?Sized> Copy for ShmToken<T> {}
 
+/// Types which can be pointed to by a [`ShmToken`], i.e. sized types and
+/// slices.
+pub trait ShmPointee {
+    /// Returns the length of a slice, or `0` for sized types.
+    fn len(ptr: NonNull<Self>) -> usize;
+    fn from_raw_parts(data: NonNull<u8>, len: usize) -> NonNull<Self>;
+}
+
+impl<T> ShmPointee for T {
+    fn len(_: NonNull<Self>) -> usize {
+        0
+    }
+    fn from_raw_parts(data: NonNull<u8>, _: usize) -> NonNull<Self> {
+        data.cast()
+    }
+}
+
+impl<T> ShmPointee for [T] {
+    fn len(ptr: NonNull<Self>) -> usize {
+        ptr.len()
+    }
+    fn from_raw_parts(data: NonNull<u8>, len: usize) -> NonNull<Self> {
+        NonNull::slice_from_raw_parts(data.cast(), len)
+    }
+}
+
 unsafe fn shm_mmap(fd: BorrowedFd, len: usize, offset: usize) -> Result<NonNull<u8>> {
     use nix::sys::mman::{MapFlags, ProtFlags};
     unsafe {
@@ -282,8 +452,3 @@ unsafe fn shm_mmap(fd: BorrowedFd, len: usize, offset: usize) -> Result<NonNull<
 fn shm_grow(fd: BorrowedFd, new_len: usize) -> Result<()> {
     nix::unistd::ftruncate(fd, new_len as i64).context("failed to grow shared memory")
 }
-
-const fn align_up(n: usize, align: usize) -> usize {
-    debug_assert!(align.is_power_of_two());
-    (n + align - 1) & !(align - 1)
-}
@@ -5,15 +5,17 @@ use std::fmt;
 use std::mem::MaybeUninit;
 use std::ptr::NonNull;
 
-use super::{Allocator, ShmHeader, ShmToken};
+use super::{Allocator, ShmHeader, ShmPointee, ShmToken};
 
 pub struct ShmBox<T: ?Sized>(NonNull<T>);
 
-impl<T: ?Sized> ShmBox<T> {
+impl<T: ?Sized + ShmPointee> ShmBox<T> {
     pub fn as_shm(this: &Self) -> ShmToken<T> {
         ShmHandle::get().get_shm(this.0)
     }
+}
 
+impl<T: ?Sized> ShmBox<T> {
     pub fn into_raw(self) -> NonNull<T> {
         let ptr = self.0;
         std::mem::forget(self);
@@ -145,10 +147,14 @@ pub struct AloHandle;
 
 impl AloHandle {
     pub fn init(shm: &'static ShmHeader) {
+        Self::init_with(shm.get_allocator())
+    }
+
+    fn init_with(alo: &'static Allocator) {
         if ALO.get().is_some() {
             panic!("allocator has been initialized");
         }
-        ALO.set(Some(shm.get_allocator()))
+        ALO.set(Some(alo))
     }
 
     pub fn get() -> &'static Allocator {
@@ -161,6 +167,16 @@ pub fn init_client(shm: &'static ShmHeader) {
     AloHandle::init(shm);
 }
 
+/// Initializes a client which replaces a dead one.
+///
+/// # Safety
+///
+/// See [`ShmHeader::reclaim_allocator`].
+pub unsafe fn reinit_client(shm: &'static ShmHeader) {
+    ShmHandle::init(shm);
+    AloHandle::init_with(unsafe { shm.reclaim_allocator() });
+}
+
 pub fn init_server(shm: &'static ShmHeader) {
     ShmHandle::init(shm);
 }
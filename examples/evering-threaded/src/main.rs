@@ -6,13 +6,13 @@ mod runtime;
 use std::collections::VecDeque;
 use std::time::Duration;
 
-use evering::uring::Uring;
+use evering::uring::{Futex, Uring, UringOverflow};
 
 use self::op::{Rqe, RqeData, Sqe, SqeData};
 use self::runtime::{Runtime, RuntimeHandle};
 
 fn main() {
-    let (sq, mut rq) = evering::uring::Builder::new().build();
+    let (sq, rq) = evering::uring::Builder::new().build();
 
     std::thread::scope(|cx| {
         cx.spawn(|| {
@@ -38,10 +38,20 @@ fn main() {
             drop(rt.into_sender());
         });
         cx.spawn(|| {
+            let mut rq = UringOverflow::new(rq);
             let mut local_queue = VecDeque::new();
             loop {
                 let mut should_exit = false;
-                if let Some(Sqe { id, data }) = rq.recv() {
+                let sqe = if local_queue.is_empty() {
+                    // Park until the client submits a request, or exit if it's gone.
+                    match rq.recv_blocking(&Futex) {
+                        Some(sqe) => Some(sqe),
+                        None => break,
+                    }
+                } else {
+                    rq.recv()
+                };
+                if let Some(Sqe { id, data }) = sqe {
                     println!("accepted task {data:?}");
                     let data = match data {
                         SqeData::Exit => {
@@ -62,15 +72,14 @@ fn main() {
                     }
                 }
 
-                if local_queue.is_empty() {
-                    std::thread::yield_now();
-                } else if should_exit || fastrand::bool() {
+                if !local_queue.is_empty() && (should_exit || fastrand::bool()) {
                     for rqe in local_queue.drain(..) {
-                        rq.send(rqe).expect("out of capacity");
+                        _ = rq.send(rqe);
                     }
                 }
 
                 if should_exit {
+                    rq.flush_blocking(&Futex);
                     break;
                 }
             }
@@ -69,6 +69,7 @@ impl local_executor::ExecutorHandle for RuntimeHandle {
 impl evering::driver::DriverHandle for RuntimeHandle {
     type Payload = RqeData;
     type Ext = ();
+    type Cancellation = evering::op::Cancellation;
     type Ref = evering_utils::runtime::DriverRef<RuntimeHandle>;
     fn get(&self) -> Self::Ref {
         evering_utils::runtime::DriverRef::new(self)
@@ -2,9 +2,10 @@ use alloc::collections::VecDeque;
 use core::cell::RefCell;
 use core::pin::Pin;
 use core::task::{Context, LocalWaker, Poll};
+use core::time::Duration;
 
 use evering::driver::{Driver, DriverHandle, OpId};
-use evering::op::{Completable, Op};
+use evering::op::{Completable, Expirable, Multishot, Op, OpStream};
 use evering::uring::Uring;
 use local_executor::{Executor, ExecutorHandle, Task};
 
@@ -41,6 +42,21 @@ impl<P, U: Uring> Runtime<P, U> {
         self.executor.block_on(fut)
     }
 
+    /// Completes all pending operations with payloads returned by `f`, and
+    /// wakes up tasks waiting to submit.
+    ///
+    /// This should be called after the remote side is closed, since no more
+    /// entries will be received.
+    pub fn fail_pending(&self, f: impl FnMut(OpId) -> P) -> usize {
+        let n = self.driver.complete_all(f);
+        // Owners of failed operations are woken up first, so that their slots
+        // are released before the submitters retry.
+        for waker in self.pending_submissions.borrow_mut().drain(..) {
+            waker.wake();
+        }
+        n
+    }
+
     pub fn into_uring(self) -> U {
         self.uring.into_inner()
     }
@@ -80,32 +96,108 @@ impl<P, U: Uring> Runtime<P, U> {
         Rt: RuntimeHandle<Payload = P, Uring = U>,
         Rt: DriverHandle<Payload = P, Ext = U::Ext>,
     {
-        let rt = RuntimeHandle::get(&handle);
+        let id = Self::submit_entry(&handle, ext, &mut data, new_entry).await;
+        Op::new(handle, id, data)
+    }
+
+    /// Submits an operation which is resolved by [`Expirable::expired`] unless
+    /// it is completed before [`Driver::expire`] reaches `deadline`.
+    pub async fn submit_with_deadline<T, Rt>(
+        handle: Rt,
+        deadline: Duration,
+        mut data: T,
+        new_entry: impl FnOnce(OpId, &mut T) -> U::A,
+    ) -> Op<T>
+    where
+        T: Expirable<Driver = Rt>,
+        Rt: RuntimeHandle<Payload = P, Uring = U>,
+        Rt: DriverHandle<Payload = P, Ext = U::Ext>,
+        U::Ext: Default,
+    {
+        let id = Self::submit_entry(&handle, <_>::default(), &mut data, new_entry).await;
+        Op::with_deadline(handle, id, data, deadline)
+    }
+
+    /// Submits a multishot operation, whose payloads are received through the
+    /// returned [`OpStream`].
+    pub async fn submit_multishot<T, Rt>(
+        handle: Rt,
+        mut data: T,
+        new_entry: impl FnOnce(OpId, &mut T) -> U::A,
+    ) -> OpStream<T>
+    where
+        T: Multishot<Driver = Rt>,
+        Rt: RuntimeHandle<Payload = P, Uring = U>,
+        Rt: DriverHandle<Payload = P, Ext = U::Ext>,
+        U::Ext: Default,
+    {
+        let id = Self::submit_entry(&handle, <_>::default(), &mut data, new_entry).await;
+        OpStream::new(handle, id, data)
+    }
+
+    async fn submit_entry<T, Rt>(
+        handle: &Rt,
+        ext: U::Ext,
+        data: &mut T,
+        new_entry: impl FnOnce(OpId, &mut T) -> U::A,
+    ) -> OpId
+    where
+        Rt: RuntimeHandle<Payload = P, Uring = U>,
+    {
+        let rt = RuntimeHandle::get(handle);
 
         let mut ext = Some(ext);
-        let id = rt
-            .wait_for_ok(|| {
-                rt.driver
-                    .try_submit_ext(ext.take().unwrap())
-                    .map_err(|e| ext = Some(e))
-            })
-            .await;
-
-        let mut ent = Some(new_entry(id, &mut data));
+        let mut new_entry = Some(new_entry);
+        // Both the operation and the entry are submitted at once, so that
+        // dropping this future never leaves an operation which is not sent.
         rt.wait_for_ok(|| {
-            rt.uring
-                .borrow_mut()
-                .send(ent.take().unwrap())
-                .map_err(|e| ent = Some(e))
+            let mut uring = rt.uring.borrow_mut();
+            // Nobody will receive the entry after the connection is closed.
+            // The operation is expected to be completed by `fail_pending`.
+            let connected = uring.is_connected();
+            let mut slot = uring.reserve(connected as usize);
+            if slot.capacity() < connected as usize {
+                return Err(());
+            }
+            let id = rt
+                .driver
+                .try_submit_ext(ext.take().unwrap())
+                .map_err(|e| ext = Some(e))?;
+            let ent = new_entry.take().unwrap()(id, data);
+            if connected {
+                _ = slot.push(ent);
+                slot.commit();
+            }
+            Ok(id)
         })
-        .await;
+        .await
+    }
 
-        Op::new(handle, id, data)
+    /// Sends cancel entries created by `new_entry` for operations cancelled
+    /// remotely, until the sending queue is full, and returns the number of
+    /// sent entries.
+    ///
+    /// See [`Driver::take_cancel`] for more information.
+    pub fn send_cancels(&self, mut new_entry: impl FnMut(OpId) -> U::A) -> usize {
+        let n = self.driver.pending_cancels();
+        let mut uring = self.uring.borrow_mut();
+        // Pending operations are completed by `fail_pending` instead.
+        if n == 0 || !uring.is_connected() {
+            return 0;
+        }
+        let mut batch = uring.reserve(n);
+        for _ in 0..batch.capacity() {
+            let id = self.driver.take_cancel().unwrap();
+            _ = batch.push(new_entry(id));
+        }
+        batch.commit()
     }
 
     async fn wait_for_ok<T>(&self, mut f: impl FnMut() -> Result<T, ()>) -> T {
         core::future::poll_fn(|cx| match f() {
             Ok(t) => Poll::Ready(t),
+            // Woken up by a received entry, or by `fail_pending` if the
+            // connection is closed.
             Err(_) => {
                 self.pending_submissions
                     .borrow_mut()
@@ -5,7 +5,7 @@ use std::mem::MaybeUninit;
 use std::os::fd::AsFd;
 use std::sync::Once;
 
-use evering::uring::Uring;
+use evering::uring::{Futex, Uring};
 use evering_ipc::*;
 use tokio::task::spawn_local;
 
@@ -33,7 +33,6 @@ pub fn bench(id: &str, iters: usize, bufsize: usize) -> Duration {
                 let mut h = UringBuilder::new();
                 h.size_a(CONCURRENCY.next_power_of_two());
                 h.size_b(CONCURRENCY.next_power_of_two());
-                let h = h.build_header();
                 shm = ShmHeader::create(shmfd, shmsize, h).unwrap();
                 rq = ServerUring::from_raw(shm.as_ref().build_raw_uring());
                 evering_ipc::shm::init_server(shm.as_ref());
@@ -43,6 +42,7 @@ pub fn bench(id: &str, iters: usize, bufsize: usize) -> Duration {
             // TODO: use async runtime
             let mut pending = None::<Rqe>;
             'outer: loop {
+                rq.heartbeat();
                 if let Some(p) = pending.take() {
                     if let Err(p) = rq.send(p) {
                         pending = Some(p);
@@ -51,7 +51,12 @@ pub fn bench(id: &str, iters: usize, bufsize: usize) -> Duration {
                     }
                 }
 
-                while let Some(Sqe { id, data }) = rq.recv() {
+                // Park while there is nothing to reply, instead of spinning.
+                let mut next = match pending {
+                    Some(_) => rq.recv(),
+                    None => rq.recv_timeout(&Futex, evering_ipc::HEARTBEAT_INTERVAL),
+                };
+                while let Some(Sqe { id, data }) = next.take() {
                     let data = match data {
                         SqeData::Exit => {
                             rq.send(Rqe {
@@ -70,11 +75,13 @@ pub fn bench(id: &str, iters: usize, bufsize: usize) -> Duration {
                             }
                             RqeData::Pong { pong: PONG }
                         },
+                        SqeData::Subscribe { .. } | SqeData::Cancel => unreachable!(),
                     };
                     if let Err(p) = rq.send(Rqe { id, data }) {
                         pending = Some(p);
                         break;
                     }
+                    next = rq.recv();
                 }
             }
 
@@ -106,7 +113,7 @@ pub fn bench(id: &str, iters: usize, bufsize: usize) -> Duration {
                                 pong,
                                 req: req_ret,
                                 resp: resp_ret,
-                            } = evering_ipc::op::ping(PING, req, resp).await;
+                            } = evering_ipc::op::ping(PING, req, resp).await.unwrap();
                             assert_eq!(pong, PONG);
                             check_respdata(bufsize, &resp_ret); // read response
                             req = req_ret;
@@ -123,7 +130,7 @@ pub fn bench(id: &str, iters: usize, bufsize: usize) -> Duration {
                     task.await.unwrap();
                 }
                 elapsed = now.elapsed();
-                evering_ipc::op::exit().await;
+                evering_ipc::op::exit().await.unwrap();
             }));
 
             _ = rx.into_uring().dispose_raw();
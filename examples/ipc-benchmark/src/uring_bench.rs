@@ -0,0 +1,77 @@
+//! Round trips of 4-byte payloads through a bare [`evering::uring`].
+//!
+//! Unlike `ipc-benchmark`, no shared memory allocation is involved, so that the
+//! cost of the ring itself, e.g. its memory layout, can be measured alone.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use evering::uring::{Builder, Uring};
+
+const CONCURRENCY: usize = 200;
+
+type Payload = [u8; 4];
+
+fn bench(iters: usize) -> Duration {
+    let mut builder = Builder::<Payload, Payload>::new();
+    builder.size_a(CONCURRENCY.next_power_of_two());
+    builder.size_b(CONCURRENCY.next_power_of_two());
+    let (mut sq, mut rq) = builder.build();
+
+    let mut elapsed = Duration::ZERO;
+    std::thread::scope(|cx| {
+        // Server
+        cx.spawn(|| {
+            let mut n = 0;
+            while n < iters {
+                let mut received = false;
+                while let Some(req) = rq.recv() {
+                    rq.send(black_box(req)).unwrap();
+                    received = true;
+                    n += 1;
+                }
+                if !received {
+                    std::thread::yield_now();
+                }
+            }
+        });
+        // Client
+        cx.spawn(|| {
+            let now = Instant::now();
+            let mut sent = sq.send_bulk(std::iter::repeat_n(*b"PING", CONCURRENCY.min(iters)));
+            let mut n = 0;
+            while n < iters {
+                let mut received = false;
+                while let Some(resp) = sq.recv() {
+                    assert_eq!(black_box(resp), *b"PING");
+                    received = true;
+                    n += 1;
+                    if sent < iters {
+                        sq.send(*b"PING").unwrap();
+                        sent += 1;
+                    }
+                }
+                if !received {
+                    std::thread::yield_now();
+                }
+            }
+            elapsed = now.elapsed();
+        });
+    });
+    elapsed
+}
+
+fn groups(c: &mut Criterion) {
+    let mut g = c.benchmark_group("uring_benchmark");
+    g.bench_function("uring_benchmark_4B", |b| {
+        b.iter_custom(|iters| bench(iters as usize))
+    });
+}
+
+criterion_group!(
+    name = uring_benchmark;
+    config = Criterion::default().sample_size(100).measurement_time(Duration::from_secs(10));
+    targets = groups
+);
+criterion_main!(uring_benchmark);